@@ -2,10 +2,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod sftp; // Include the refactored sftp module
+use sftp::queue::TransferQueue;
 use sftp::*; // Import all re-exported functions from the sftp module
 
 mod types;
-use std::sync::Mutex;
 use types::ConnectionManager;
 
 fn main() {
@@ -13,25 +13,51 @@ fn main() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(Mutex::new(ConnectionManager::new()))
+        .manage(ConnectionManager::new())
+        .manage(TransferQueue::new())
+        .setup(|app| {
+            spawn_keepalive_task(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             connect_sftp,
             disconnect_sftp,
+            respond_keyboard_interactive,
             upload_file,
+            upload_directory,
             download_file,
+            download_directory,
             delete_item,
             rename_item,
             list_directory,
+            stat_item,
             create_directory,
             delete_directory,
             delete_directory_recursive,
             fetch_directory_size,
             cancel_directory_size,
             fetch_storage_info,
+            set_permissions,
+            set_owner,
             cancel_transfer,
+            pause_transfer,
             get_active_transfers,
+            resume_transfer,
+            get_resumable_transfers,
+            discard_transfer,
+            get_transfer_queue,
+            set_transfer_limits,
+            move_transfer,
+            prioritize_transfer,
+            remove_transfer,
+            clear_finished_transfers,
             copy_item,
             move_item,
+            list_known_hosts,
+            accept_host_key,
+            remove_known_host,
+            list_ssh_config_hosts,
+            resolve_ssh_config_host,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
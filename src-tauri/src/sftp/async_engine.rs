@@ -0,0 +1,578 @@
+use crate::sftp::directory_operations::DeleteProgress;
+use crate::sftp::engine::LinkTarget;
+use crate::sftp::error::{unless_transport, SftpError};
+use crate::sftp::file_operations::{DownloadProgress, TransferContext};
+use crate::sftp::tunnel::open_subsystem;
+use crate::sftp::utils::{is_hidden_path, run_blocking};
+use crate::sftp::walk::{walk_root, walk_step, SymlinkWalk, WalkDir, WalkLookup, WalkStep};
+use crate::types::*;
+use bytes::BytesMut;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, FuturesOrdered, FuturesUnordered, StreamExt, TryStreamExt};
+use openssh_sftp_client::file::File as RemoteFile;
+use openssh_sftp_client::metadata::{MetaData, Permissions};
+use openssh_sftp_client::{Sftp, SftpOptions};
+use ssh2::{FileStat, Session};
+use std::collections::BTreeSet;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use tauri::{Emitter, Window};
+use tokio::io::AsyncSeekExt;
+
+// Size of one read/write request; the client splits it further if the server's limits are lower
+const CHUNK_SIZE: usize = 64 * 1024;
+// Requests kept in flight per transfer
+const PIPELINE_DEPTH: usize = 16;
+// Entries of one directory processed concurrently by recursive operations
+const DIRECTORY_CONCURRENCY: usize = 16;
+
+// Start the sftp subsystem for the async backend on a channel of the connection's
+// authenticated session. The channel is pumped through a loopback socket, which
+// the client drives with its pipelined requests.
+pub async fn open_async_sftp(session: &Session, socket: TcpStream) -> Result<Arc<Sftp>, SftpError> {
+    let session = session.clone();
+    let stream = run_blocking(move || open_subsystem(session, socket, "sftp"))
+        .await?
+        .map_err(SftpError::other)?;
+
+    stream
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to set up SFTP stream: {}", e))?;
+    let stream = tokio::net::TcpStream::from_std(stream)
+        .map_err(|e| format!("Failed to set up SFTP stream: {}", e))?;
+    let (reader, writer) = stream.into_split();
+
+    let sftp = Sftp::new(writer, reader, SftpOptions::new())
+        .await
+        .map_err(|e| SftpError::sftp("Failed to start SFTP session", e))?;
+
+    Ok(Arc::new(sftp))
+}
+
+pub async fn read_dir(sftp: &Sftp, path: &str) -> Result<Vec<(PathBuf, FileStat)>, SftpError> {
+    let mut fs = sftp.fs();
+    let entries: Vec<_> = fs
+        .open_dir(path)
+        .await
+        .map_err(|e| {
+            SftpError::sftp(&format!("Failed to read directory {}", path), e).with_path(path)
+        })?
+        .read_dir()
+        .try_collect()
+        .await
+        .map_err(|e| {
+            SftpError::sftp(&format!("Failed to read directory {}", path), e).with_path(path)
+        })?;
+
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.filename() != Path::new(".") && entry.filename() != Path::new(".."))
+        .map(|entry| {
+            (
+                Path::new(path).join(entry.filename()),
+                file_stat(&entry.metadata()),
+            )
+        })
+        .collect())
+}
+
+pub async fn lstat(sftp: &Sftp, path: &str) -> Result<FileStat, SftpError> {
+    sftp.fs()
+        .symlink_metadata(path)
+        .await
+        .map(|metadata| file_stat(&metadata))
+        .map_err(|e| SftpError::sftp("Failed to stat", e).with_path(path))
+}
+
+pub async fn stat(sftp: &Sftp, path: &str) -> Result<FileStat, SftpError> {
+    sftp.fs()
+        .metadata(path)
+        .await
+        .map(|metadata| file_stat(&metadata))
+        .map_err(|e| SftpError::sftp("Failed to stat", e).with_path(path))
+}
+
+// Up to `len` bytes of a remote file from `offset`, fewer at the end of the file
+pub async fn read_at(
+    sftp: &Sftp,
+    path: &str,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>, SftpError> {
+    let remote_file = sftp
+        .open(path)
+        .await
+        .map_err(|e| SftpError::sftp("Failed to open remote file", e).with_path(path))?;
+    let chunk = read_chunk(remote_file, offset, len).await?;
+    Ok(chunk.to_vec())
+}
+
+pub async fn ensure_dir(sftp: &Sftp, path: &str) -> Result<(), SftpError> {
+    let error = match sftp.fs().create_dir(path).await {
+        Ok(()) => return Ok(()),
+        Err(e) => SftpError::sftp("Failed to create directory", e).with_path(path),
+    };
+    match sftp.fs().metadata(path).await {
+        Ok(metadata) if metadata.file_type().is_some_and(|t| t.is_dir()) => Ok(()),
+        _ => Err(error),
+    }
+}
+
+// Resolve several links concurrently, in the order given
+pub async fn resolve_links(sftp: &Sftp, paths: &[String]) -> Result<Vec<LinkTarget>, SftpError> {
+    stream::iter(paths)
+        .map(|path| resolve_link(sftp, path))
+        .buffered(DIRECTORY_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+async fn resolve_link(sftp: &Sftp, path: &str) -> Result<LinkTarget, SftpError> {
+    let error = |e| SftpError::sftp("Failed to resolve link", e).with_path(path);
+    let target = sftp
+        .fs()
+        .read_link(path)
+        .await
+        .map(|target| target.to_string_lossy().replace("\\", "/"))
+        .map_err(error);
+    let stat = sftp
+        .fs()
+        .metadata(path)
+        .await
+        .map(|metadata| file_stat(&metadata))
+        .map_err(error);
+
+    // A failed lookup only fails the listing when the connection broke
+    Ok(LinkTarget {
+        path: unless_transport(target)?,
+        stat: unless_transport(stat)?,
+    })
+}
+
+impl WalkLookup for Sftp {
+    async fn link_stat(&self, path: &str) -> Result<FileStat, SftpError> {
+        lstat(self, path).await
+    }
+
+    async fn target_stat(&self, path: &str) -> Result<FileStat, SftpError> {
+        self.fs()
+            .metadata(path)
+            .await
+            .map(|metadata| file_stat(&metadata))
+            .map_err(|e| SftpError::sftp("Failed to resolve link", e).with_path(path))
+    }
+
+    async fn canonical_path(&self, path: &str) -> Result<String, SftpError> {
+        self.fs()
+            .canonicalize(path)
+            .await
+            .map(|canonical| canonical.to_string_lossy().replace("\\", "/"))
+            .map_err(|e| SftpError::sftp("Failed to resolve path", e).with_path(path))
+    }
+}
+
+// Delete a directory tree, removing the entries of each directory concurrently
+pub fn remove_dir_all<'a>(
+    sftp: &'a Sftp,
+    path: &'a str,
+    progress: &'a DeleteProgress,
+) -> BoxFuture<'a, Result<(), SftpError>> {
+    Box::pin(async move {
+        let entries = read_dir(sftp, path).await?;
+
+        // Futures are created up front but only polled DIRECTORY_CONCURRENCY at a time
+        let removals: Vec<BoxFuture<'_, Result<(), SftpError>>> = entries
+            .into_iter()
+            .map(|(child, stat)| -> BoxFuture<'_, Result<(), SftpError>> {
+                Box::pin(async move {
+                    let child = child.to_string_lossy().replace("\\", "/");
+                    // Links are removed as links, even when they point at a directory
+                    if stat.is_dir() && !stat.file_type().is_symlink() {
+                        remove_dir_all(sftp, &child, progress).await
+                    } else {
+                        sftp.fs().remove_file(&child).await.map_err(|e| {
+                            SftpError::sftp(&format!("Failed to delete file {}", child), e)
+                                .with_path(&child)
+                        })?;
+                        progress.entry_deleted(&child);
+                        Ok(())
+                    }
+                })
+            })
+            .collect();
+
+        stream::iter(removals)
+            .buffer_unordered(DIRECTORY_CONCURRENCY)
+            .try_collect::<()>()
+            .await?;
+
+        sftp.fs().remove_dir(path).await.map_err(|e| {
+            SftpError::sftp(&format!("Failed to delete directory {}", path), e).with_path(path)
+        })
+    })
+}
+
+pub async fn directory_size(
+    sftp: &Sftp,
+    path: &str,
+    symlinks: SymlinkPolicy,
+    cancel_flag: &AtomicBool,
+    window: &Window,
+    operation_id: &str,
+) -> Result<(u64, u64), SftpError> {
+    let symlinks = SymlinkWalk::new(symlinks);
+    let root = match walk_root(sftp, path, &symlinks).await? {
+        WalkStep::Directory(root) => root,
+        // A root that isn't walked into counts like any such entry
+        step => return Ok(step.size().map_or((0, 0), |size| (size, 1))),
+    };
+    let walk = SizeWalk {
+        sftp,
+        symlinks,
+        cancel_flag,
+        window,
+        operation_id,
+        total_size: AtomicU64::new(0),
+        file_count: AtomicU32::new(0),
+    };
+
+    walk.walk(&root).await?;
+    Ok((
+        walk.total_size.load(Ordering::Relaxed),
+        walk.file_count.load(Ordering::Relaxed) as u64,
+    ))
+}
+
+// Shared state of a concurrent directory size walk
+struct SizeWalk<'a> {
+    sftp: &'a Sftp,
+    symlinks: SymlinkWalk,
+    cancel_flag: &'a AtomicBool,
+    window: &'a Window,
+    operation_id: &'a str,
+    total_size: AtomicU64,
+    file_count: AtomicU32,
+}
+
+impl<'a> SizeWalk<'a> {
+    fn walk<'w>(&'w self, dir: &'w WalkDir) -> BoxFuture<'w, Result<(), SftpError>> {
+        Box::pin(async move {
+            let path = dir.path.as_str();
+            self.check_cancelled(path)?;
+
+            let entries = read_dir(self.sftp, path).await?;
+            let mut subdirectories = Vec::new();
+
+            for (file_path, stat) in entries {
+                let file_path = file_path.to_string_lossy().replace("\\", "/");
+
+                let step = walk_step(self.sftp, dir, &file_path, &stat, &self.symlinks);
+                let size = match step.await? {
+                    WalkStep::Directory(subdirectory) => {
+                        subdirectories.push(subdirectory);
+                        continue;
+                    }
+                    WalkStep::Skip => continue,
+                    step => step.size().unwrap_or(0),
+                };
+
+                let total_size = self.total_size.fetch_add(size, Ordering::Relaxed) + size;
+                let file_count = self.file_count.fetch_add(1, Ordering::Relaxed) + 1;
+
+                // Emit progress every 100 files to avoid overwhelming the frontend
+                if file_count.is_multiple_of(100) {
+                    self.window
+                        .emit(
+                            "directory_size_progress",
+                            serde_json::json!({
+                                "operation_id": self.operation_id,
+                                "current_path": file_path,
+                                "current_size": total_size,
+                                "files_processed": file_count,
+                                "is_hidden": is_hidden_path(&file_path)
+                            }),
+                        )
+                        .ok();
+                }
+            }
+
+            // Futures are created up front but only polled DIRECTORY_CONCURRENCY at a time
+            let walks: Vec<_> = subdirectories
+                .iter()
+                .map(|subdirectory| self.walk(subdirectory))
+                .collect();
+
+            stream::iter(walks)
+                .buffer_unordered(DIRECTORY_CONCURRENCY)
+                .try_collect::<()>()
+                .await
+        })
+    }
+
+    fn check_cancelled(&self, path: &str) -> Result<(), SftpError> {
+        if !self.cancel_flag.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        self.window
+            .emit(
+                "directory_size_cancelled",
+                serde_json::json!({
+                    "operation_id": self.operation_id,
+                    "path": path
+                }),
+            )
+            .ok();
+        Err(SftpError::cancelled("Operation cancelled by user"))
+    }
+}
+
+// Upload from `*written` on with up to PIPELINE_DEPTH writes in flight, advancing
+// `written` past each acknowledged write. Returns whether the transfer was cancelled.
+pub async fn upload(
+    sftp: &Sftp,
+    local_path: &str,
+    remote_path: &str,
+    written: &mut u64,
+    context: &TransferContext,
+) -> Result<bool, SftpError> {
+    let start = *written;
+    let mut local_file = std::fs::File::open(local_path)
+        .map_err(|e| SftpError::local("Failed to open local file", e).with_path(local_path))?;
+
+    let total_size = local_file
+        .metadata()
+        .map_err(|e| SftpError::local("Failed to stat local file", e).with_path(local_path))?
+        .len();
+
+    let remote_file = if start > 0 {
+        // Keep what an earlier attempt wrote; chunks are written at explicit offsets
+        local_file
+            .seek(SeekFrom::Start(start))
+            .map_err(|e| SftpError::local("Failed to resume upload", e).with_path(local_path))?;
+        sftp.options()
+            .write(true)
+            .create(true)
+            .open(remote_path)
+            .await
+    } else {
+        sftp.create(remote_path).await
+    }
+    .map_err(|e| SftpError::sftp("Failed to create remote file", e).with_path(remote_path))?;
+
+    let mut in_flight = FuturesUnordered::new();
+    let mut outstanding = BTreeSet::new(); // Offsets of the writes in flight
+    let mut offset = start;
+    let mut transferred = start;
+    let mut end_of_file = false;
+
+    loop {
+        // A paused upload lets the writes in flight land, then waits
+        let paused = context.paused();
+        if paused && in_flight.is_empty() {
+            context.wait_while_paused().await;
+            if context.cancelled("upload") {
+                return Ok(true);
+            }
+            continue;
+        }
+
+        while !paused && !end_of_file && in_flight.len() < PIPELINE_DEPTH {
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+            let n = local_file
+                .read(&mut chunk)
+                .map_err(|e| SftpError::local("Read error", e).with_path(local_path))?;
+            if n == 0 {
+                end_of_file = true;
+                break;
+            }
+            chunk.truncate(n);
+
+            outstanding.insert(offset);
+            in_flight.push(write_chunk(remote_file.clone(), offset, chunk));
+            offset += n as u64;
+        }
+
+        let (chunk_offset, len) = match in_flight.next().await {
+            Some(chunk) => chunk.map_err(|e| e.with_path(remote_path))?,
+            None => break,
+        };
+        outstanding.remove(&chunk_offset);
+        transferred += len;
+        // Writes land out of order; everything before the oldest one in flight is on the server
+        *written = outstanding.first().copied().unwrap_or(offset);
+
+        context.emit_progress("upload", remote_path, transferred, total_size);
+        if context.cancelled("upload") {
+            return Ok(true);
+        }
+    }
+
+    remote_file
+        .close()
+        .await
+        .map_err(|e| SftpError::sftp("Failed to close remote file", e).with_path(remote_path))?;
+
+    Ok(false)
+}
+
+// Write a chunk at `offset`, returning the offset and length written
+async fn write_chunk(
+    mut file: RemoteFile,
+    offset: u64,
+    chunk: Vec<u8>,
+) -> Result<(u64, u64), SftpError> {
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(|e| SftpError::other(format!("Write error: {}", e)))?;
+    file.write_all(&chunk)
+        .await
+        .map_err(|e| SftpError::sftp("Write error", e))?;
+
+    Ok((offset, chunk.len() as u64))
+}
+
+// Download from `progress.transferred` onwards with up to PIPELINE_DEPTH reads
+// in flight, writing chunks locally in order. Returns whether the transfer was cancelled.
+pub async fn download(
+    sftp: &Sftp,
+    remote_path: &str,
+    progress: &mut DownloadProgress,
+    context: &TransferContext,
+) -> Result<bool, SftpError> {
+    let mut remote_file = sftp
+        .open(remote_path)
+        .await
+        .map_err(|e| SftpError::sftp("Failed to open remote file", e).with_path(remote_path))?;
+
+    // Get file size for progress
+    let total_size = remote_file
+        .metadata()
+        .await
+        .map_err(|e| SftpError::sftp("Failed to stat remote file", e).with_path(remote_path))?
+        .len()
+        .unwrap_or(0);
+
+    let mut in_flight = FuturesOrdered::new();
+    let mut offset = progress.transferred;
+
+    loop {
+        // A paused download writes out the reads in flight, then waits
+        let paused = context.paused();
+        if paused && in_flight.is_empty() {
+            context.wait_while_paused().await;
+            if context.cancelled("download") {
+                return Ok(true);
+            }
+            continue;
+        }
+
+        // Reads past the end come back empty and are dropped below
+        while !paused && in_flight.len() < PIPELINE_DEPTH {
+            in_flight.push_back(read_chunk(remote_file.clone(), offset, CHUNK_SIZE));
+            offset += CHUNK_SIZE as u64;
+        }
+
+        let chunk = match in_flight.next().await {
+            Some(chunk) => chunk.map_err(|e| e.with_path(remote_path))?,
+            None => break,
+        };
+        if chunk.is_empty() {
+            break;
+        }
+
+        progress
+            .local_file
+            .write_all(&chunk)
+            .map_err(|e| SftpError::local("Write error", e).with_path(&progress.local_path))?;
+        progress.transferred += chunk.len() as u64;
+
+        context.emit_progress("download", remote_path, progress.transferred, total_size);
+        if context.cancelled("download") {
+            return Ok(true);
+        }
+
+        // A short chunk means the end of the file was reached
+        if chunk.len() < CHUNK_SIZE {
+            break;
+        }
+    }
+
+    Ok(false)
+}
+
+// Read `len` bytes at `offset`, or fewer at the end of the file
+async fn read_chunk(mut file: RemoteFile, offset: u64, len: usize) -> Result<BytesMut, SftpError> {
+    let mut chunk = BytesMut::with_capacity(len);
+
+    while chunk.len() < len {
+        // Position every request explicitly, reads may return less than asked for
+        file.seek(SeekFrom::Start(offset + chunk.len() as u64))
+            .await
+            .map_err(|e| SftpError::other(format!("Read error: {}", e)))?;
+
+        let remaining = (len - chunk.len()) as u32;
+        let buffer = BytesMut::with_capacity(remaining as usize);
+        match file
+            .read(remaining, buffer)
+            .await
+            .map_err(|e| SftpError::sftp("Read error", e))?
+        {
+            Some(data) if !data.is_empty() => chunk.extend_from_slice(&data),
+            _ => break,
+        }
+    }
+
+    Ok(chunk)
+}
+
+// Convert metadata to ssh2's FileStat so both backends hand callers the same type
+fn file_stat(metadata: &MetaData) -> FileStat {
+    let file_type = match metadata.file_type() {
+        Some(file_type) if file_type.is_dir() => 0o040000,
+        Some(file_type) if file_type.is_symlink() => 0o120000,
+        Some(file_type) if file_type.is_file() => 0o100000,
+        Some(file_type) if file_type.is_fifo() => 0o010000,
+        Some(file_type) if file_type.is_socket() => 0o140000,
+        Some(file_type) if file_type.is_block_device() => 0o060000,
+        Some(file_type) if file_type.is_char_device() => 0o020000,
+        _ => 0,
+    };
+
+    let perm = match (file_type, metadata.permissions()) {
+        (0, None) => None,
+        (file_type, permissions) => Some(file_type | permissions.map(mode_bits).unwrap_or(0)),
+    };
+
+    FileStat {
+        size: metadata.len(),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        perm,
+        atime: metadata.accessed().map(|time| time.into_raw() as u64),
+        mtime: metadata.modified().map(|time| time.into_raw() as u64),
+    }
+}
+
+fn mode_bits(permissions: Permissions) -> u32 {
+    [
+        (permissions.suid(), 0o4000),
+        (permissions.sgid(), 0o2000),
+        (permissions.svtx(), 0o1000),
+        (permissions.read_by_owner(), 0o400),
+        (permissions.write_by_owner(), 0o200),
+        (permissions.execute_by_owner(), 0o100),
+        (permissions.read_by_group(), 0o040),
+        (permissions.write_by_group(), 0o020),
+        (permissions.execute_by_group(), 0o010),
+        (permissions.read_by_other(), 0o004),
+        (permissions.write_by_other(), 0o002),
+        (permissions.execute_by_other(), 0o001),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .fold(0, |mode, (_, bit)| mode | bit)
+}
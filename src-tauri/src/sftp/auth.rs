@@ -0,0 +1,297 @@
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::types::*;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use ssh2::{KeyboardInteractivePrompt, Prompt, Session};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+// How long a keyboard-interactive prompt waits for the user before giving up
+const KEYBOARD_INTERACTIVE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AuthMethod {
+    Agent,
+    KeyFile,
+    Password,
+    KeyboardInteractive,
+}
+
+impl AuthMethod {
+    // Name of the method as listed by the server in SSH_MSG_USERAUTH_FAILURE
+    fn protocol_name(self) -> &'static str {
+        match self {
+            AuthMethod::Agent | AuthMethod::KeyFile => "publickey",
+            AuthMethod::Password => "password",
+            AuthMethod::KeyboardInteractive => "keyboard-interactive",
+        }
+    }
+}
+
+// Authenticate a handshaked session using every method the config provides.
+// The server is asked which methods it accepts before each attempt, so a
+// rejected method falls through to the next one, and a "partial success"
+// (e.g. publickey followed by an OTP) continues with the remaining methods.
+pub fn authenticate<P: KeyboardInteractivePrompt>(
+    session: &Session,
+    config: &ConnectionConfig,
+    prompter: &mut P,
+) -> Result<(), SftpError> {
+    let mut candidates = Vec::new();
+    if config.use_agent.unwrap_or(false) {
+        candidates.push(AuthMethod::Agent);
+    }
+    if config.private_key_path.is_some() {
+        candidates.push(AuthMethod::KeyFile);
+    }
+    if config.password.is_some() {
+        candidates.push(AuthMethod::Password);
+    }
+    candidates.push(AuthMethod::KeyboardInteractive);
+
+    let mut errors = Vec::new();
+
+    loop {
+        let offered = session
+            .auth_methods(&config.username)
+            .map_err(|e| SftpError::ssh("Failed to query authentication methods", e))?
+            .to_string();
+
+        if session.authenticated() {
+            return Ok(());
+        }
+
+        let offered: Vec<&str> = offered.split(',').map(str::trim).collect();
+        let method = match candidates
+            .iter()
+            .position(|method| offered.contains(&method.protocol_name()))
+        {
+            Some(index) => candidates.remove(index),
+            None => break,
+        };
+
+        let result = match method {
+            AuthMethod::Agent => authenticate_with_agent(session, config),
+            AuthMethod::KeyFile => {
+                let key_path = config.private_key_path.as_deref().unwrap_or_default();
+                session
+                    .userauth_pubkey_file(
+                        &config.username,
+                        None,
+                        Path::new(key_path),
+                        config.passphrase.as_deref(),
+                    )
+                    .map_err(|e| SftpError::ssh("Key authentication failed", e).with_path(key_path))
+            }
+            AuthMethod::Password => session
+                .userauth_password(
+                    &config.username,
+                    config.password.as_deref().unwrap_or_default(),
+                )
+                .map_err(|e| SftpError::ssh("Password authentication failed", e)),
+            AuthMethod::KeyboardInteractive => {
+                // libssh2 counts the time the user spends answering against the
+                // session timeout, so it is lifted for the duration of the exchange
+                let timeout = session.timeout();
+                session.set_timeout(0);
+                let result = session
+                    .userauth_keyboard_interactive(&config.username, prompter)
+                    .map_err(|e| SftpError::ssh("Keyboard-interactive authentication failed", e));
+                session.set_timeout(timeout);
+                result
+            }
+        };
+
+        if session.authenticated() {
+            return Ok(());
+        }
+
+        match result {
+            // A dropped connection is not a rejected login
+            Err(error) if error.transport_lost => return Err(error),
+            Err(error) => errors.push(error),
+            Ok(()) => {}
+        }
+    }
+
+    if errors.is_empty() {
+        return Err(SftpError::new(
+            ErrorKind::AuthFailed,
+            "No authentication method provided",
+        ));
+    }
+    // Every rejection goes into one message; the key file, if it was one of
+    // them, is the path
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    let mut error = SftpError::new(ErrorKind::AuthFailed, messages.join("; "));
+    error.path = errors.into_iter().find_map(|error| error.path);
+    Err(error)
+}
+
+// Offer the identities held by the SSH agent at SSH_AUTH_SOCK, optionally
+// restricted to the one whose comment or fingerprint matches `agent_identity`
+fn authenticate_with_agent(session: &Session, config: &ConnectionConfig) -> Result<(), SftpError> {
+    let mut agent = session
+        .agent()
+        .map_err(|e| SftpError::ssh("Failed to initialize SSH agent", e))?;
+    agent
+        .connect()
+        .map_err(|e| SftpError::ssh("Failed to connect to SSH agent", e))?;
+    agent
+        .list_identities()
+        .map_err(|e| SftpError::ssh("Failed to list SSH agent identities", e))?;
+
+    let identities = agent
+        .identities()
+        .map_err(|e| SftpError::ssh("Failed to read SSH agent identities", e))?;
+
+    let pinned = config
+        .agent_identity
+        .as_deref()
+        .filter(|pin| !pin.is_empty());
+    let mut offered = 0;
+
+    for identity in identities.iter() {
+        if let Some(pin) = pinned {
+            let fingerprint = agent_key_fingerprint(identity.blob());
+            let matches = identity.comment() == pin
+                || fingerprint == pin
+                || fingerprint.trim_start_matches("SHA256:") == pin;
+            if !matches {
+                continue;
+            }
+        }
+
+        offered += 1;
+        if agent.userauth(&config.username, identity).is_ok() && session.authenticated() {
+            agent.disconnect().ok();
+            return Ok(());
+        }
+    }
+
+    agent.disconnect().ok();
+
+    match (pinned, offered) {
+        (Some(pin), 0) => Err(SftpError::new(
+            ErrorKind::AuthFailed,
+            format!("SSH agent has no identity matching {}", pin),
+        )),
+        (None, 0) => Err(SftpError::new(
+            ErrorKind::AuthFailed,
+            "SSH agent has no identities",
+        )),
+        _ => Err(SftpError::new(
+            ErrorKind::AuthFailed,
+            "Agent authentication failed: no identity was accepted",
+        )),
+    }
+}
+
+// OpenSSH style SHA256 fingerprint of a public key blob
+fn agent_key_fingerprint(blob: &[u8]) -> String {
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(blob)))
+}
+
+// Forwards keyboard-interactive prompts to the frontend and blocks the
+// authenticating thread until the answers arrive via respond_keyboard_interactive
+pub struct InteractivePrompter {
+    connection_id: String,
+    host: String,
+    app: AppHandle,
+    password: Option<String>,
+}
+
+impl InteractivePrompter {
+    pub fn new(connection_id: &str, app: &AppHandle, config: &ConnectionConfig) -> Self {
+        InteractivePrompter {
+            connection_id: connection_id.to_string(),
+            host: config.host.clone(),
+            app: app.clone(),
+            password: config.password.clone(),
+        }
+    }
+}
+
+impl KeyboardInteractivePrompt for InteractivePrompter {
+    fn prompt<'a>(
+        &mut self,
+        username: &str,
+        instructions: &str,
+        prompts: &[Prompt<'a>],
+    ) -> Vec<String> {
+        if prompts.is_empty() {
+            return Vec::new();
+        }
+
+        // Answer a lone password prompt with the saved password once before asking the user
+        if prompts.len() == 1
+            && !prompts[0].echo
+            && prompts[0].text.to_lowercase().contains("password")
+        {
+            if let Some(password) = self.password.take() {
+                return vec![password];
+            }
+        }
+
+        let (responder, responses) = mpsc::channel();
+        let connections = self.app.state::<ConnectionManager>();
+        connections.set_pending_auth(&self.connection_id, responder);
+
+        self.app
+            .emit(
+                "keyboard_interactive_prompt",
+                serde_json::json!({
+                    "connection_id": self.connection_id,
+                    "host": self.host,
+                    "username": username,
+                    "instructions": instructions,
+                    "prompts": prompts
+                        .iter()
+                        .map(|prompt| serde_json::json!({
+                            "text": prompt.text,
+                            "echo": prompt.echo
+                        }))
+                        .collect::<Vec<_>>()
+                }),
+            )
+            .ok();
+
+        let answers = responses
+            .recv_timeout(KEYBOARD_INTERACTIVE_TIMEOUT)
+            .unwrap_or_default();
+
+        connections.take_pending_auth(&self.connection_id);
+
+        answers
+    }
+}
+
+// Answer the pending keyboard-interactive prompt of a connection that is still authenticating
+#[tauri::command]
+pub async fn respond_keyboard_interactive(
+    connection_id: String,
+    responses: Vec<String>,
+    connections: State<'_, ConnectionManager>,
+) -> Result<(), SftpError> {
+    let responder = connections
+        .take_pending_auth(&connection_id)
+        .ok_or_else(|| {
+            SftpError::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No authentication prompt pending for connection {}",
+                    connection_id
+                ),
+            )
+        })?;
+
+    responder.send(responses).map_err(|_| {
+        SftpError::new(
+            ErrorKind::Cancelled,
+            "Authentication attempt is no longer waiting for a response",
+        )
+    })
+}
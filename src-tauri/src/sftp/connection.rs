@@ -1,67 +1,253 @@
+use crate::sftp::async_engine::open_async_sftp;
+use crate::sftp::auth::{authenticate, InteractivePrompter};
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::sftp::known_hosts::verify_host_key;
+use crate::sftp::proxy::connect_via_proxy;
+use crate::sftp::reconnect::reconnect;
+use crate::sftp::tunnel::open_tunnel;
+use crate::sftp::utils::{connect_with_timeout, run_blocking};
 use crate::types::*;
 use ssh2::Session;
+use std::io;
 use std::net::TcpStream;
 use std::path::Path;
-use std::sync::Mutex;
-use tauri::State;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State, Window};
 use uuid::Uuid;
 
-type ConnectionManagerState = Mutex<ConnectionManager>;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 15;
+const DEFAULT_HANDSHAKE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_OPERATION_TIMEOUT_SECS: u64 = 60;
+
+// How often every live session is checked, and sent an SSH keepalive when idle
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
 
 #[tauri::command]
 pub async fn connect_sftp(
     config: ConnectionConfig,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<String, String> {
+    connections: State<'_, ConnectionManager>,
+    window: Window,
+) -> Result<String, SftpError> {
     let connection_id = Uuid::new_v4().to_string();
 
-    let tcp = TcpStream::connect(format!("{}:{}", config.host, config.port))
-        .map_err(|e| format!("Failed to connect: {}", e))?;
+    let established = establish_connection(&config, &connection_id, window.app_handle()).await?;
 
-    let mut session = Session::new().map_err(|e| format!("Failed to create session: {}", e))?;
-    session.set_tcp_stream(tcp);
-    session
-        .handshake()
-        .map_err(|e| format!("SSH handshake failed: {}", e))?;
-
-    if let Some(private_key_path) = &config.private_key_path {
-        session
-            .userauth_pubkey_file(
-                &config.username,
-                None,
-                Path::new(private_key_path),
-                config.passphrase.as_deref(),
-            )
-            .map_err(|e| format!("Key authentication failed: {}", e))?;
-    } else if let Some(password) = &config.password {
-        session
-            .userauth_password(&config.username, password)
-            .map_err(|e| format!("Password authentication failed: {}", e))?;
-    } else {
-        return Err("No authentication method provided".to_string());
+    // The config is kept with the session so a dropped connection can be re-established
+    connections.insert(connection_id.clone(), established, config);
+
+    Ok(connection_id)
+}
+
+// Connect the session used for commands and, when the async SFTP backend is
+// selected, start the channel that carries its pipelined requests on it
+pub async fn establish_connection(
+    config: &ConnectionConfig,
+    connection_id: &str,
+    app: &AppHandle,
+) -> Result<EstablishedConnection, SftpError> {
+    // The handshake may wait on the user for keyboard-interactive answers,
+    // so it runs on a blocking thread instead of the async runtime
+    let (session, socket) = run_blocking({
+        let config = config.clone();
+        let connection_id = connection_id.to_string();
+        let app = app.clone();
+        move || establish_session(&config, &connection_id, &app)
+    })
+    .await??;
+
+    let async_sftp = match config.sftp_backend.unwrap_or(SftpBackend::Ssh2) {
+        SftpBackend::Ssh2 => None,
+        SftpBackend::Async => Some(open_async_sftp(&session, socket).await?),
+    };
+
+    Ok(EstablishedConnection {
+        session,
+        async_sftp,
+    })
+}
+
+// Connect to the target, tunnelling through each configured jump host in order.
+// Failures on a hop are reported with the hop number so they can be told apart
+// from failures at the target. The session comes with a handle to the socket
+// it talks over, for tunnels that wait on it.
+pub fn establish_session(
+    config: &ConnectionConfig,
+    connection_id: &str,
+    app: &AppHandle,
+) -> Result<(Session, TcpStream), SftpError> {
+    let jump_hosts = config.jump_hosts.as_deref().unwrap_or_default();
+    let mut tunnel: Option<TcpStream> = None;
+
+    for (index, hop) in jump_hosts.iter().enumerate() {
+        let label = format!("Jump host {} ({}:{})", index + 1, hop.host, hop.port);
+
+        let tcp = match tunnel.take() {
+            Some(tcp) => tcp,
+            None => connect_tcp(hop, config.proxy.as_ref()).map_err(|e| e.context(&label))?,
+        };
+        let socket = tcp
+            .try_clone()
+            .map_err(|e| SftpError::local("Failed to set up tunnel", e).context(&label))?;
+        let session = open_session(tcp, hop, connection_id, app).map_err(|e| e.context(&label))?;
+
+        let next = jump_hosts.get(index + 1).unwrap_or(config);
+        tunnel = Some(
+            open_tunnel(session, socket, &next.host, next.port).map_err(|e| {
+                SftpError::new(ErrorKind::ConnectionFailed, format!("{}: {}", label, e))
+            })?,
+        );
     }
 
+    let tcp = match tunnel {
+        Some(tcp) => tcp,
+        None => connect_tcp(config, config.proxy.as_ref())?,
+    };
+    let socket = tcp
+        .try_clone()
+        .map_err(|e| SftpError::local("Failed to set up connection", e))?;
+
+    Ok((open_session(tcp, config, connection_id, app)?, socket))
+}
+
+// Open the socket to the first hop, directly or through the configured proxy
+fn connect_tcp(
+    target: &ConnectionConfig,
+    proxy: Option<&ProxyConfig>,
+) -> Result<TcpStream, SftpError> {
+    let timeout = Duration::from_secs(
+        target
+            .connect_timeout_secs
+            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+    );
+
+    let connected = match proxy {
+        Some(proxy) => connect_via_proxy(proxy, &target.host, target.port, timeout),
+        None => connect_with_timeout(&target.host, target.port, timeout)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to connect: {}", e))),
+    };
+
+    connected.map_err(|e| {
+        let kind = match e.kind() {
+            io::ErrorKind::TimedOut => ErrorKind::Timeout,
+            _ => ErrorKind::ConnectionFailed,
+        };
+        SftpError::new(kind, e.to_string())
+    })
+}
+
+// Handshake over an established stream, verify the host key and authenticate
+fn open_session(
+    tcp: TcpStream,
+    config: &ConnectionConfig,
+    connection_id: &str,
+    app: &AppHandle,
+) -> Result<Session, SftpError> {
+    let handshake_timeout = config
+        .handshake_timeout_secs
+        .unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT_SECS);
+    let operation_timeout = config
+        .operation_timeout_secs
+        .unwrap_or(DEFAULT_OPERATION_TIMEOUT_SECS);
+
+    let mut session = Session::new().map_err(|e| SftpError::ssh("Failed to create session", e))?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(timeout_millis(handshake_timeout));
+    session.handshake().map_err(|e| {
+        let error = SftpError::ssh("SSH handshake failed", e);
+        match error.kind {
+            ErrorKind::Timeout => error,
+            _ => SftpError {
+                kind: ErrorKind::ConnectionFailed,
+                ..error
+            },
+        }
+    })?;
+
+    verify_host_key(&session, &config.host, config.port, app)?;
+
+    let mut prompter = InteractivePrompter::new(connection_id, app, config);
+    authenticate(&session, config, &mut prompter)?;
+
     if !session.authenticated() {
-        return Err("Authentication failed".to_string());
+        return Err(SftpError::new(
+            ErrorKind::AuthFailed,
+            "Authentication failed",
+        ));
     }
 
-    connections
-        .lock()
-        .unwrap()
-        .insert(connection_id.clone(), session);
+    // A timeout of 0 means blocking calls wait forever
+    session.set_timeout(timeout_millis(operation_timeout));
+    session.set_keepalive(true, KEEPALIVE_INTERVAL.as_secs() as u32);
 
-    Ok(connection_id)
+    Ok(session)
+}
+
+fn timeout_millis(secs: u64) -> u32 {
+    secs.saturating_mul(1000).min(u32::MAX as u64) as u32
+}
+
+// Background task that checks every session with an SFTP round trip. A
+// keepalive that merely gets sent can't tell a half-open link, so a reply that
+// doesn't come within the operation timeout counts as a lost transport. Each
+// connection is checked and reconnected on its own task, so one slow server
+// doesn't hold up the others; failed reconnects drop the connection with a
+// connection_lost event.
+pub fn spawn_keepalive_task(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(KEEPALIVE_INTERVAL).await;
+
+            let connections = app.state::<ConnectionManager>().all();
+
+            for (connection_id, connection) in connections {
+                let (pool, generation, reconnecting) = {
+                    let connection = connection.lock().unwrap();
+                    (
+                        connection.sftp_pool.clone(),
+                        connection.generation,
+                        connection.reconnecting.clone(),
+                    )
+                };
+
+                // A reconnect in progress replaces this session anyway
+                if reconnecting.try_lock().is_err() {
+                    continue;
+                }
+
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    let probe = run_blocking(move || {
+                        pool.with_channel(|sftp| {
+                            sftp.realpath(Path::new("."))
+                                .map_err(|e| SftpError::ssh("Keepalive failed", e))
+                        })
+                    })
+                    .await;
+
+                    let alive = match probe {
+                        Ok(Ok(_)) => true,
+                        Ok(Err(e)) => !e.transport_lost && e.kind != ErrorKind::Timeout,
+                        Err(_) => false,
+                    };
+                    if !alive {
+                        reconnect(&app, &connection_id, generation).await.ok();
+                    }
+                });
+            }
+        }
+    });
 }
 
 #[tauri::command]
 pub async fn disconnect_sftp(
     connection_id: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<(), String> {
-    let mut conn_manager = connections.lock().unwrap();
-
-    if let Some(mut session) = conn_manager.remove(&connection_id) {
-        let _ = session.disconnect(None, "User disconnected", None);
+    connections: State<'_, ConnectionManager>,
+) -> Result<(), SftpError> {
+    if let Some(session) = connections.remove(&connection_id) {
+        run_blocking(move || {
+            let _ = session.disconnect(None, "User disconnected", None);
+        })
+        .await?;
     }
 
     Ok(())
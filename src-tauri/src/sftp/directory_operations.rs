@@ -1,65 +1,78 @@
-use crate::sftp::utils::format_permissions;
+use crate::sftp::engine::LinkTarget;
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::sftp::owners::resolve_owners;
+use crate::sftp::reconnect::{current_pool, with_engine, with_reconnect};
+use crate::sftp::utils::{entry_type, format_permissions, is_hidden, is_hidden_path};
+use crate::sftp::walk::{on_ssh2, walk_step, SymlinkWalk, WalkDir, WalkStep};
 use crate::types::*;
 use once_cell::sync::Lazy;
+use ssh2::FileStat;
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc, Mutex,
 };
 use std::time::Duration;
-use tauri::{Emitter, State, Window};
+use tauri::{AppHandle, Emitter, Manager, Window};
 use uuid::Uuid;
 
-type ConnectionManagerState = Mutex<ConnectionManager>;
-
 // Global directory size calculation cancel manager
 static DIRECTORY_SIZE_CANCEL_MAP: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+// List a directory. Dotfiles are included when `show_hidden` is set, falling
+// back to the connection's show_hidden default when the call doesn't say.
 #[tauri::command]
 pub async fn list_directory(
     connection_id: String,
     path: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<Vec<FileItem>, String> {
-    let mut conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get_mut(&connection_id)
-        .ok_or("Connection not found")?;
-
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
+    show_hidden: Option<bool>,
+    app: AppHandle,
+) -> Result<Vec<FileItem>, SftpError> {
+    let show_hidden = match show_hidden {
+        Some(show_hidden) => show_hidden,
+        None => app
+            .state::<ConnectionManager>()
+            .config(&connection_id)
+            .and_then(|config| config.show_hidden)
+            .unwrap_or(false),
+    };
+
+    // readdir reports the links themselves, so their targets are looked up
+    // separately; a link that can't be followed is flagged, not an error
+    let path = &path;
+    let (entries, targets) = with_engine(&app, &connection_id, true, |engine| async move {
+        let entries = engine.read_dir(path).await?;
+        let links = entries
+            .iter()
+            .filter(|(_, stat)| stat.file_type().is_symlink())
+            .map(|(file_path, _)| file_path.to_string_lossy().replace("\\", "/"))
+            .collect();
+        let targets = engine.resolve_links(links).await?;
+        Ok((entries, targets))
+    })
+    .await?;
+
+    let mut targets = targets.into_iter();
     let mut files = Vec::new();
 
-    let dir_path = Path::new(&path);
-    let entries = sftp
-        .readdir(dir_path)
-        .map_err(|e| format!("Failed to read directory: {}", e))?;
-
     for (file_path, stat) in entries {
-        let name = file_path
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string();
-
-        if name.starts_with('.') && name != ".." {
-            continue; // Skip hidden files for simplicity
-        }
+        let target = if stat.file_type().is_symlink() {
+            targets.next()
+        } else {
+            None
+        };
 
-        files.push(FileItem {
-            name,
-            path: file_path.to_string_lossy().replace("\\", "/"),
-            is_directory: stat.is_dir(),
-            size: stat.size.unwrap_or(0),
-            modified: stat.mtime.unwrap_or(0),
-            permissions: format_permissions(stat.perm.unwrap_or(0)),
-        });
+        let file = file_item(&file_path.to_string_lossy(), &stat, target);
+        if file.is_hidden && !show_hidden {
+            continue;
+        }
+        files.push(file);
     }
 
+    resolve_owners(&app, &connection_id, &mut files).await;
+
     // Sort: directories first, then files
     files.sort_by(|a, b| {
         if a.name == ".." {
@@ -78,25 +91,74 @@ pub async fn list_directory(
     Ok(files)
 }
 
+// Every attribute of a single path, as list_directory reports it
 #[tauri::command]
-pub async fn create_directory(
+pub async fn stat_item(
     connection_id: String,
     path: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<(), String> {
-    let mut conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get_mut(&connection_id)
-        .ok_or("Connection not found")?;
-
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
+    app: AppHandle,
+) -> Result<FileItem, SftpError> {
+    let path = &path;
+    let (stat, target) = with_engine(&app, &connection_id, true, |engine| async move {
+        let stat = engine.lstat(path).await?;
+        let target = if stat.file_type().is_symlink() {
+            engine.resolve_links(vec![path.clone()]).await?.pop()
+        } else {
+            None
+        };
+        Ok((stat, target))
+    })
+    .await?;
+
+    let mut file = file_item(path, &stat, target);
+    resolve_owners(&app, &connection_id, std::slice::from_mut(&mut file)).await;
+    Ok(file)
+}
 
-    sftp.mkdir(Path::new(&path), 0o755)
-        .map_err(|e| format!("Failed to create directory: {}", e))?;
+// Listing entry of a path, from the stat of the entry itself and, for a
+// symlink, what it points at. Owner and group names are filled in afterwards.
+fn file_item(path: &str, stat: &FileStat, target: Option<LinkTarget>) -> FileItem {
+    let path = path.replace("\\", "/");
+    let name = Path::new(&path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let is_symlink = stat.file_type().is_symlink();
+    let target_stat = target.as_ref().and_then(|target| target.stat.as_ref());
+
+    FileItem {
+        is_hidden: is_hidden(&name),
+        name,
+        path,
+        is_directory: stat.is_dir(),
+        size: stat.size.unwrap_or(0),
+        modified: stat.mtime.unwrap_or(0),
+        permissions: format_permissions(stat.perm.unwrap_or(0)),
+        is_symlink,
+        link_target: target.as_ref().and_then(|target| target.path.clone()),
+        target_type: target_stat.map(entry_type),
+        is_broken_link: is_symlink && target_stat.is_none(),
+        mode: stat.perm.unwrap_or(0),
+        uid: stat.uid,
+        gid: stat.gid,
+        owner: None,
+        group: None,
+        accessed: stat.atime.unwrap_or(0),
+    }
+}
 
-    Ok(())
+#[tauri::command]
+pub async fn create_directory(
+    connection_id: String,
+    path: String,
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    with_reconnect(&app, &connection_id, false, move |sftp| {
+        sftp.mkdir(Path::new(&path), 0o755)
+            .map_err(|e| SftpError::ssh("Failed to create directory", e).with_path(&path))
+    })
+    .await
 }
 
 // Delete directory with files (recursive)
@@ -104,29 +166,63 @@ pub async fn create_directory(
 pub async fn delete_directory_recursive(
     connection_id: String,
     path: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<(), String> {
-    let mut conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get_mut(&connection_id)
-        .ok_or("Connection not found")?;
-
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-    delete_directory_recursive_helper(&sftp, &path)?;
-    Ok(())
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    let (path, app_ref) = (&path, &app);
+    with_engine(&app, &connection_id, false, |engine| async move {
+        engine.remove_dir_all(path, app_ref).await
+    })
+    .await
+}
+
+// Files and links removed by a recursive delete. Like directory_size_progress,
+// delete_progress goes out every 100 entries and says whether the current one is
+// hidden, since the walk removes dotfiles the listing may not show.
+pub struct DeleteProgress {
+    app: AppHandle,
+    root: String,
+    deleted: AtomicU64,
+}
+
+impl DeleteProgress {
+    pub fn new(app: &AppHandle, root: &str) -> Self {
+        DeleteProgress {
+            app: app.clone(),
+            root: root.to_string(),
+            deleted: AtomicU64::new(0),
+        }
+    }
+
+    pub fn entry_deleted(&self, path: &str) {
+        let deleted = self.deleted.fetch_add(1, Ordering::Relaxed) + 1;
+        if deleted.is_multiple_of(100) {
+            self.app
+                .emit(
+                    "delete_progress",
+                    serde_json::json!({
+                        "path": self.root,
+                        "current_path": path,
+                        "files_deleted": deleted,
+                        "is_hidden": is_hidden_path(path)
+                    }),
+                )
+                .ok();
+        }
+    }
 }
 
 // Helper function for recursive directory deletion
-fn delete_directory_recursive_helper(sftp: &ssh2::Sftp, path: &str) -> Result<(), String> {
+pub fn delete_directory_recursive_helper(
+    sftp: &ssh2::Sftp,
+    path: &str,
+    progress: &DeleteProgress,
+) -> Result<(), SftpError> {
     let dir_path = Path::new(path);
 
     // List directory contents
-    let entries = sftp
-        .readdir(dir_path)
-        .map_err(|e| format!("Failed to read directory {}: {}", path, e))?;
+    let entries = sftp.readdir(dir_path).map_err(|e| {
+        SftpError::ssh(&format!("Failed to read directory {}", path), e).with_path(path)
+    })?;
 
     // Delete all contents first
     for (file_path, stat) in entries {
@@ -139,19 +235,24 @@ fn delete_directory_recursive_helper(sftp: &ssh2::Sftp, path: &str) -> Result<()
             continue;
         }
 
-        if stat.is_dir() {
+        // Links are removed as links, even when they point at a directory
+        if stat.is_dir() && !stat.file_type().is_symlink() {
             // Recursively delete subdirectory
-            delete_directory_recursive_helper(sftp, &file_path_str)?;
+            delete_directory_recursive_helper(sftp, &file_path_str, progress)?;
         } else {
-            // Delete file
-            sftp.unlink(&file_path)
-                .map_err(|e| format!("Failed to delete file {}: {}", file_path_str, e))?;
+            // Delete file or link
+            sftp.unlink(&file_path).map_err(|e| {
+                SftpError::ssh(&format!("Failed to delete file {}", file_path_str), e)
+                    .with_path(&file_path_str)
+            })?;
+            progress.entry_deleted(&file_path_str);
         }
     }
 
     // Now delete the empty directory
-    sftp.rmdir(dir_path)
-        .map_err(|e| format!("Failed to delete directory {}: {}", path, e))?;
+    sftp.rmdir(dir_path).map_err(|e| {
+        SftpError::ssh(&format!("Failed to delete directory {}", path), e).with_path(path)
+    })?;
 
     Ok(())
 }
@@ -161,36 +262,27 @@ fn delete_directory_recursive_helper(sftp: &ssh2::Sftp, path: &str) -> Result<()
 pub async fn delete_directory(
     connection_id: String,
     path: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<(), String> {
-    let mut conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get_mut(&connection_id)
-        .ok_or("Connection not found")?;
-
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-    sftp.rmdir(Path::new(&path))
-        .map_err(|e| format!("Failed to delete directory: {}", e))?;
-
-    Ok(())
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    with_reconnect(&app, &connection_id, false, move |sftp| {
+        sftp.rmdir(Path::new(&path))
+            .map_err(|e| SftpError::ssh("Failed to delete directory", e).with_path(&path))
+    })
+    .await
 }
 
-// Fetch directory size (recursive) with cancellation support
+// Fetch directory size (recursive) with cancellation support. Symlinks are not
+// counted unless `symlinks` says otherwise.
 #[tauri::command]
 pub async fn fetch_directory_size(
     connection_id: String,
     path: String,
-    connections: State<'_, ConnectionManagerState>,
+    symlinks: Option<SymlinkPolicy>,
     window: Window,
-) -> Result<String, String> {
-    let conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get(&connection_id)
-        .ok_or("Connection not found")?
-        .clone();
+) -> Result<String, SftpError> {
+    let symlinks = symlinks.unwrap_or(SymlinkPolicy::Skip);
+    let app = window.app_handle().clone();
+    current_pool(&app, &connection_id)?;
 
     // Generate a unique operation ID and create a cancel flag
     let operation_id = Uuid::new_v4().to_string();
@@ -207,19 +299,16 @@ pub async fn fetch_directory_size(
         async move {
             let result = tokio::time::timeout(
                 Duration::from_secs(300), // 5 minutes timeout
-                async {
-                    let sftp = session
-                        .sftp()
-                        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-                    calculate_directory_size_recursive(
-                        &sftp,
-                        &path,
-                        &cancel_flag,
-                        &window,
-                        &operation_id,
-                    )
-                },
+                // Sizing only reads, so it is retried after a reconnect
+                with_engine(&app, &connection_id, true, |engine| {
+                    let (path, window) = (path.clone(), window.clone());
+                    let (operation_id, cancel_flag) = (operation_id.clone(), cancel_flag.clone());
+                    async move {
+                        engine
+                            .directory_size(&path, symlinks, cancel_flag, &window, &operation_id)
+                            .await
+                    }
+                }),
             )
             .await;
 
@@ -230,7 +319,7 @@ pub async fn fetch_directory_size(
                 .remove(&operation_id);
 
             match result {
-                Ok(Ok(size)) => {
+                Ok(Ok((size, _))) => {
                     // Emit success event
                     window
                         .emit(
@@ -266,7 +355,11 @@ pub async fn fetch_directory_size(
                             serde_json::json!({
                                 "operation_id": operation_id,
                                 "path": path,
-                                "error": "Operation timed out after 5 minutes",
+                                "error": SftpError::new(
+                                    ErrorKind::Timeout,
+                                    "Operation timed out after 5 minutes"
+                                )
+                                .with_path(&path),
                                 "success": false
                             }),
                         )
@@ -274,7 +367,7 @@ pub async fn fetch_directory_size(
                 }
             }
 
-            Ok::<(), String>(())
+            Ok::<(), SftpError>(())
         }
     });
 
@@ -283,28 +376,35 @@ pub async fn fetch_directory_size(
 
 // Cancel directory size calculation
 #[tauri::command]
-pub async fn cancel_directory_size(operation_id: String) -> Result<(), String> {
+pub async fn cancel_directory_size(operation_id: String) -> Result<(), SftpError> {
     let cancel_map = DIRECTORY_SIZE_CANCEL_MAP.lock().unwrap();
 
     if let Some(cancel_flag) = cancel_map.get(&operation_id) {
         cancel_flag.store(true, Ordering::Relaxed);
         Ok(())
     } else {
-        Err(format!(
-            "Directory size operation with ID {} not found or already completed",
-            operation_id
+        Err(SftpError::new(
+            ErrorKind::NotFound,
+            format!(
+                "Directory size operation with ID {} not found or already completed",
+                operation_id
+            ),
         ))
     }
 }
 
-// Helper function for recursive directory size calculation with cancellation
-fn calculate_directory_size_recursive(
+// Helper function for recursive directory size calculation with cancellation.
+// Returns the total size and the number of files counted.
+pub fn calculate_directory_size_recursive(
     sftp: &ssh2::Sftp,
-    path: &str,
+    dir: &WalkDir,
+    symlinks: &SymlinkWalk,
     cancel_flag: &Arc<AtomicBool>,
     window: &Window,
     operation_id: &str,
-) -> Result<u64, String> {
+) -> Result<(u64, u64), SftpError> {
+    let path = dir.path.as_str();
+
     // Check for cancellation at the start of each directory
     if cancel_flag.load(Ordering::Relaxed) {
         window
@@ -316,17 +416,18 @@ fn calculate_directory_size_recursive(
                 }),
             )
             .ok();
-        return Err("Operation cancelled by user".to_string());
+        return Err(SftpError::cancelled("Operation cancelled by user"));
     }
 
     let dir_path = Path::new(path);
     let mut total_size = 0u64;
     let mut file_count = 0u32;
+    let mut subdir_files = 0u64;
 
     // List directory contents
-    let entries = sftp
-        .readdir(dir_path)
-        .map_err(|e| format!("Failed to read directory {}: {}", path, e))?;
+    let entries = sftp.readdir(dir_path).map_err(|e| {
+        SftpError::ssh(&format!("Failed to read directory {}", path), e).with_path(path)
+    })?;
 
     for (file_path, stat) in entries {
         // Check for cancellation during iteration
@@ -340,7 +441,7 @@ fn calculate_directory_size_recursive(
                     }),
                 )
                 .ok();
-            return Err("Operation cancelled by user".to_string());
+            return Err(SftpError::cancelled("Operation cancelled by user"));
         }
 
         // Skip . and .. entries
@@ -352,19 +453,26 @@ fn calculate_directory_size_recursive(
 
         let file_path_str = file_path.to_string_lossy().replace("\\", "/");
 
-        if stat.is_dir() {
-            // Recursively calculate subdirectory size
-            let subdir_size = calculate_directory_size_recursive(
-                sftp,
-                &file_path_str,
-                cancel_flag,
-                window,
-                operation_id,
-            )?;
-            total_size = total_size.saturating_add(subdir_size);
-        } else {
+        let file_size = match on_ssh2(walk_step(sftp, dir, &file_path_str, &stat, symlinks))? {
+            WalkStep::Directory(subdir) => {
+                // Recursively calculate subdirectory size
+                let (subdir_size, files) = calculate_directory_size_recursive(
+                    sftp,
+                    &subdir,
+                    symlinks,
+                    cancel_flag,
+                    window,
+                    operation_id,
+                )?;
+                total_size = total_size.saturating_add(subdir_size);
+                subdir_files += files;
+                None
+            }
+            step => step.size(),
+        };
+
+        if let Some(file_size) = file_size {
             // Add file size
-            let file_size = stat.size.unwrap_or(0);
             total_size = total_size.saturating_add(file_size);
             file_count += 1;
 
@@ -377,7 +485,8 @@ fn calculate_directory_size_recursive(
                             "operation_id": operation_id,
                             "current_path": file_path_str,
                             "current_size": total_size,
-                            "files_processed": file_count
+                            "files_processed": file_count,
+                            "is_hidden": is_hidden(&name)
                         }),
                     )
                     .ok();
@@ -385,5 +494,5 @@ fn calculate_directory_size_recursive(
         }
     }
 
-    Ok(total_size)
+    Ok((total_size, subdir_files + file_count as u64))
 }
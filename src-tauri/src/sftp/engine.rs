@@ -0,0 +1,341 @@
+use crate::sftp::async_engine;
+use crate::sftp::directory_operations::{
+    calculate_directory_size_recursive, delete_directory_recursive_helper, DeleteProgress,
+};
+use crate::sftp::error::{unless_transport, SftpError};
+use crate::sftp::file_operations::{
+    download_blocking, upload_blocking, DownloadProgress, TransferContext,
+};
+use crate::sftp::pool::SftpPool;
+use crate::sftp::utils::run_blocking;
+use crate::sftp::walk::{on_ssh2, walk_root, SymlinkWalk, WalkStep};
+use crate::types::SymlinkPolicy;
+use openssh_sftp_client::Sftp;
+use ssh2::FileStat;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tauri::{AppHandle, Window};
+
+// Backend the SFTP traffic of a connection goes through. Both offer the same
+// operations: ssh2 runs them on the blocking pool one request at a time, the
+// async backend keeps many requests in flight on its own channel.
+#[derive(Clone)]
+pub enum SftpEngine {
+    Ssh2(Arc<SftpPool>),
+    Async(Arc<Sftp>),
+}
+
+impl SftpEngine {
+    // Entries of a directory with their full paths, without "." and ".."
+    pub async fn read_dir(&self, path: &str) -> Result<Vec<(PathBuf, FileStat)>, SftpError> {
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, path) = (pool.clone(), path.to_string());
+                blocking(move || {
+                    pool.with_channel(|sftp| {
+                        sftp.readdir(Path::new(&path)).map_err(|e| {
+                            SftpError::ssh("Failed to read directory", e).with_path(&path)
+                        })
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => async_engine::read_dir(sftp, path).await,
+        }
+    }
+
+    // Attributes of a path itself, without following a final symlink
+    pub async fn lstat(&self, path: &str) -> Result<FileStat, SftpError> {
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, path) = (pool.clone(), path.to_string());
+                blocking(move || {
+                    pool.with_channel(|sftp| {
+                        sftp.lstat(Path::new(&path))
+                            .map_err(|e| SftpError::ssh("Failed to stat", e).with_path(&path))
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => async_engine::lstat(sftp, path).await,
+        }
+    }
+
+    // Attributes of a path, following symlinks
+    pub async fn stat(&self, path: &str) -> Result<FileStat, SftpError> {
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, path) = (pool.clone(), path.to_string());
+                blocking(move || {
+                    pool.with_channel(|sftp| {
+                        sftp.stat(Path::new(&path))
+                            .map_err(|e| SftpError::ssh("Failed to stat", e).with_path(&path))
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => async_engine::stat(sftp, path).await,
+        }
+    }
+
+    // Up to `len` bytes of a remote file from `offset`, fewer at the end of the file
+    pub async fn read_at(&self, path: &str, offset: u64, len: usize) -> Result<Vec<u8>, SftpError> {
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, path) = (pool.clone(), path.to_string());
+                blocking(move || {
+                    pool.with_channel(|sftp| {
+                        let read_error = |e| SftpError::io("Read error", e, pool.session());
+                        let mut file = sftp.open(Path::new(&path)).map_err(|e| {
+                            SftpError::ssh("Failed to open remote file", e).with_path(&path)
+                        })?;
+                        file.seek(SeekFrom::Start(offset)).map_err(read_error)?;
+                        let mut data = Vec::with_capacity(len);
+                        file.take(len as u64)
+                            .read_to_end(&mut data)
+                            .map_err(read_error)?;
+                        Ok(data)
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => async_engine::read_at(sftp, path, offset, len).await,
+        }
+    }
+
+    // Where each symlink points and the stat of what it resolves to
+    pub async fn resolve_links(&self, paths: Vec<String>) -> Result<Vec<LinkTarget>, SftpError> {
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let pool = pool.clone();
+                blocking(move || {
+                    pool.with_channel(|sftp| {
+                        paths.iter().map(|path| resolve_link(sftp, path)).collect()
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => async_engine::resolve_links(sftp, &paths).await,
+        }
+    }
+
+    // Create a directory unless one already exists at the path
+    pub async fn ensure_dir(&self, path: &str) -> Result<(), SftpError> {
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, path) = (pool.clone(), path.to_string());
+                blocking(move || {
+                    pool.with_channel(|sftp| match sftp.mkdir(Path::new(&path), 0o755) {
+                        Ok(()) => Ok(()),
+                        // OpenSSH reports an existing path as a generic failure
+                        Err(e) => {
+                            match sftp.stat(Path::new(&path)) {
+                                Ok(stat) if stat.is_dir() => Ok(()),
+                                _ => Err(SftpError::ssh("Failed to create directory", e)
+                                    .with_path(&path)),
+                            }
+                        }
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => async_engine::ensure_dir(sftp, path).await,
+        }
+    }
+
+    // Delete a directory and everything below it. A link to a directory is
+    // removed as a link, so nothing outside the selected tree is touched.
+    // Progress goes out as delete_progress.
+    pub async fn remove_dir_all(&self, path: &str, app: &AppHandle) -> Result<(), SftpError> {
+        let progress = DeleteProgress::new(app, path);
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, path) = (pool.clone(), path.to_string());
+                blocking(move || {
+                    pool.with_channel(|sftp| {
+                        let stat = sftp
+                            .lstat(Path::new(&path))
+                            .map_err(|e| SftpError::ssh("Failed to stat", e).with_path(&path))?;
+                        if stat.file_type().is_symlink() {
+                            return sftp.unlink(Path::new(&path)).map_err(|e| {
+                                SftpError::ssh("Failed to delete link", e).with_path(&path)
+                            });
+                        }
+                        delete_directory_recursive_helper(sftp, &path, &progress)
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => {
+                if async_engine::lstat(sftp, path)
+                    .await?
+                    .file_type()
+                    .is_symlink()
+                {
+                    return sftp
+                        .fs()
+                        .remove_file(path)
+                        .await
+                        .map_err(|e| SftpError::sftp("Failed to delete link", e).with_path(path));
+                }
+                async_engine::remove_dir_all(sftp, path, &progress).await
+            }
+        }
+    }
+
+    // Total size and number of the files below a directory, emitting directory_size_progress
+    pub async fn directory_size(
+        &self,
+        path: &str,
+        symlinks: SymlinkPolicy,
+        cancel_flag: Arc<AtomicBool>,
+        window: &Window,
+        operation_id: &str,
+    ) -> Result<(u64, u64), SftpError> {
+        match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, path) = (pool.clone(), path.to_string());
+                let (window, operation_id) = (window.clone(), operation_id.to_string());
+                blocking(move || {
+                    pool.with_channel(|sftp| {
+                        let symlinks = SymlinkWalk::new(symlinks);
+                        let root = match on_ssh2(walk_root(sftp, &path, &symlinks))? {
+                            WalkStep::Directory(root) => root,
+                            // A root that isn't walked into counts like any such entry
+                            step => return Ok(step.size().map_or((0, 0), |size| (size, 1))),
+                        };
+                        calculate_directory_size_recursive(
+                            sftp,
+                            &root,
+                            &symlinks,
+                            &cancel_flag,
+                            &window,
+                            &operation_id,
+                        )
+                    })
+                })
+                .await
+            }
+            SftpEngine::Async(sftp) => {
+                async_engine::directory_size(
+                    sftp,
+                    path,
+                    symlinks,
+                    &cancel_flag,
+                    window,
+                    operation_id,
+                )
+                .await
+            }
+        }
+    }
+
+    // Upload a local file from byte `start` on, keeping what the remote file
+    // holds before it. Hands back how far the remote file is known to be written,
+    // so the caller can resume on a new engine, with whether the transfer was cancelled.
+    pub async fn upload(
+        &self,
+        local_path: &str,
+        remote_path: &str,
+        start: u64,
+        context: &TransferContext,
+    ) -> Result<(u64, Result<bool, SftpError>), SftpError> {
+        let mut written = start;
+        let (written, result) = match self {
+            SftpEngine::Ssh2(pool) => {
+                let pool = pool.clone();
+                let (local_path, remote_path) = (local_path.to_string(), remote_path.to_string());
+                let context = context.clone();
+                run_blocking(move || {
+                    let result = pool.with_channel(|sftp| {
+                        upload_blocking(
+                            sftp,
+                            pool.session(),
+                            &local_path,
+                            &remote_path,
+                            &mut written,
+                            &context,
+                        )
+                    });
+                    (written, result)
+                })
+                .await?
+            }
+            SftpEngine::Async(sftp) => {
+                let result =
+                    async_engine::upload(sftp, local_path, remote_path, &mut written, context)
+                        .await;
+                (written, result)
+            }
+        };
+        Ok((written, result.map_err(|e| e.with_path(remote_path))))
+    }
+
+    // Download from `progress.transferred` onwards, handing the progress back so
+    // the caller can resume on a new engine, with whether the transfer was cancelled
+    pub async fn download(
+        &self,
+        remote_path: &str,
+        mut progress: DownloadProgress,
+        context: &TransferContext,
+    ) -> Result<(DownloadProgress, Result<bool, SftpError>), SftpError> {
+        let (progress, result) = match self {
+            SftpEngine::Ssh2(pool) => {
+                let (pool, remote_path) = (pool.clone(), remote_path.to_string());
+                let context = context.clone();
+                run_blocking(move || {
+                    let result = pool.with_channel(|sftp| {
+                        download_blocking(
+                            sftp,
+                            pool.session(),
+                            &remote_path,
+                            &mut progress,
+                            &context,
+                        )
+                    });
+                    (progress, result)
+                })
+                .await?
+            }
+            SftpEngine::Async(sftp) => {
+                let result =
+                    async_engine::download(sftp, remote_path, &mut progress, context).await;
+                (progress, result)
+            }
+        };
+        Ok((progress, result.map_err(|e| e.with_path(remote_path))))
+    }
+}
+
+// A symlink's target path and the stat of what it resolves to. Either is None
+// when the server can't provide it, e.g. for a dangling or looping link.
+pub struct LinkTarget {
+    pub path: Option<String>,
+    pub stat: Option<FileStat>,
+}
+
+fn resolve_link(sftp: &ssh2::Sftp, path: &str) -> Result<LinkTarget, SftpError> {
+    let error = |e| SftpError::ssh("Failed to resolve link", e).with_path(path);
+    let target = sftp
+        .readlink(Path::new(path))
+        .map(|target| target.to_string_lossy().replace("\\", "/"))
+        .map_err(error);
+    let stat = sftp.stat(Path::new(path)).map_err(error);
+
+    // A failed lookup only fails the listing when the connection broke
+    Ok(LinkTarget {
+        path: unless_transport(target)?,
+        stat: unless_transport(stat)?,
+    })
+}
+
+// run_blocking for work that reports an SftpError
+async fn blocking<T, F>(work: F) -> Result<T, SftpError>
+where
+    F: FnOnce() -> Result<T, SftpError> + Send + 'static,
+    T: Send + 'static,
+{
+    run_blocking(work).await.map_err(SftpError::other)?
+}
@@ -0,0 +1,379 @@
+use serde::Serialize;
+use ssh2::{ErrorCode, Session};
+use std::fmt;
+use std::io;
+
+// libssh2 session error codes that mean the SSH transport itself is gone
+const TRANSPORT_ERROR_CODES: &[i32] = &[
+    -2,  // LIBSSH2_ERROR_BANNER_RECV
+    -4,  // LIBSSH2_ERROR_INVALID_MAC
+    -7,  // LIBSSH2_ERROR_SOCKET_SEND
+    -9,  // LIBSSH2_ERROR_TIMEOUT
+    -12, // LIBSSH2_ERROR_DECRYPT
+    -13, // LIBSSH2_ERROR_SOCKET_DISCONNECT
+    -14, // LIBSSH2_ERROR_PROTO
+    -26, // LIBSSH2_ERROR_CHANNEL_CLOSED
+    -30, // LIBSSH2_ERROR_SOCKET_TIMEOUT
+    -43, // LIBSSH2_ERROR_SOCKET_RECV
+    -45, // LIBSSH2_ERROR_BAD_SOCKET
+];
+
+// libssh2 session error codes of a transport that timed out
+const TIMEOUT_ERROR_CODES: &[i32] = &[
+    -9,  // LIBSSH2_ERROR_TIMEOUT
+    -30, // LIBSSH2_ERROR_SOCKET_TIMEOUT
+];
+
+// libssh2 session error codes of a rejected login
+const AUTH_ERROR_CODES: &[i32] = &[
+    -16, // LIBSSH2_ERROR_FILE (unreadable key file)
+    -18, // LIBSSH2_ERROR_AUTHENTICATION_FAILED
+    -19, // LIBSSH2_ERROR_PUBLICKEY_UNVERIFIED
+    -48, // LIBSSH2_ERROR_KEYFILE_AUTH_FAILED
+];
+
+// SFTP status codes (draft-ietf-secsh-filexfer) with a kind of their own
+const SFTP_NO_SUCH_FILE: i32 = 2;
+const SFTP_PERMISSION_DENIED: i32 = 3;
+const SFTP_NO_CONNECTION: i32 = 6;
+const SFTP_CONNECTION_LOST: i32 = 7;
+const SFTP_OP_UNSUPPORTED: i32 = 8;
+const SFTP_NO_SUCH_PATH: i32 = 10;
+const SFTP_FILE_ALREADY_EXISTS: i32 = 11;
+const SFTP_WRITE_PROTECT: i32 = 12;
+const SFTP_NO_SPACE_ON_FILESYSTEM: i32 = 14;
+const SFTP_QUOTA_EXCEEDED: i32 = 15;
+const SFTP_DIR_NOT_EMPTY: i32 = 18;
+
+// Stable category of a failure the frontend can branch on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    NotEmpty,
+    NoSpace,
+    ConnectionFailed, // The server could not be reached or the handshake failed
+    ConnectionLost,   // An established connection dropped
+    AuthFailed,
+    HostKeyUnknown,
+    HostKeyChanged,
+    Cancelled,
+    Timeout,
+    Unsupported,
+    InvalidInput,
+    Other,
+}
+
+// Error returned by every command: a kind, the message and, where one is
+// involved, the path. Serialized as { kind, message, path }.
+#[derive(Debug, Clone, Serialize)]
+pub struct SftpError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub path: Option<String>,
+    // Whether the SSH transport itself broke, so the connection needs re-establishing
+    #[serde(skip)]
+    pub transport_lost: bool,
+    // Whether the SFTP channel the operation ran on is unusable
+    #[serde(skip)]
+    pub channel_failed: bool,
+}
+
+impl SftpError {
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        SftpError {
+            kind,
+            message: message.into(),
+            path: None,
+            transport_lost: false,
+            channel_failed: false,
+        }
+    }
+
+    pub fn other<S: Into<String>>(message: S) -> Self {
+        SftpError::new(ErrorKind::Other, message)
+    }
+
+    pub fn cancelled<S: Into<String>>(message: S) -> Self {
+        SftpError::new(ErrorKind::Cancelled, message)
+    }
+
+    pub fn connection_not_found() -> Self {
+        SftpError::new(ErrorKind::ConnectionLost, "Connection not found")
+    }
+
+    pub fn ssh(context: &str, error: ssh2::Error) -> Self {
+        SftpError {
+            kind: ssh_error_kind(&error),
+            transport_lost: is_transport_error(&error),
+            channel_failed: matches!(error.code(), ErrorCode::Session(_)),
+            ..SftpError::other(format!("{}: {}", context, error))
+        }
+    }
+
+    // Reads and writes on remote files surface io::Error, which drops the libssh2
+    // error code, so the session is asked for its last error instead
+    pub fn io(context: &str, error: io::Error, session: &Session) -> Self {
+        let last = ssh2::Error::last_session_error(session);
+        let transport_lost = matches!(
+            error.kind(),
+            io::ErrorKind::TimedOut
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+        ) || last.as_ref().map(is_transport_error).unwrap_or(false);
+        let channel_failed = transport_lost
+            || matches!(
+                last.as_ref().map(|last| last.code()),
+                Some(ErrorCode::Session(_))
+            );
+
+        let kind = match &last {
+            Some(last) if transport_lost => ssh_error_kind(last),
+            _ if transport_lost => ErrorKind::ConnectionLost,
+            _ => io_error_kind(&error),
+        };
+
+        SftpError {
+            kind,
+            transport_lost,
+            channel_failed,
+            ..SftpError::other(format!("{}: {}", context, error))
+        }
+    }
+
+    // Failures of local files; they never affect the connection
+    pub fn local(context: &str, error: io::Error) -> Self {
+        SftpError::new(io_error_kind(&error), format!("{}: {}", context, error))
+    }
+
+    // Errors of the async backend: anything but a status reply from the server
+    // means its channel is gone, and the backend with it until a reconnect
+    pub fn sftp(context: &str, error: openssh_sftp_client::Error) -> Self {
+        use openssh_sftp_client::error::SftpErrorKind;
+        use openssh_sftp_client::Error;
+
+        let transport_lost = matches!(
+            error,
+            Error::IOError(_)
+                | Error::AwaitableError(_)
+                | Error::BackgroundTaskFailure(_)
+                | Error::SftpServerFailure(_)
+                | Error::TaskJoinError(_)
+        );
+        let kind = match &error {
+            Error::SftpError(SftpErrorKind::NoSuchFile, _) => ErrorKind::NotFound,
+            Error::SftpError(SftpErrorKind::PermDenied, _) => ErrorKind::PermissionDenied,
+            Error::SftpError(SftpErrorKind::OpUnsupported, _) => ErrorKind::Unsupported,
+            Error::SftpError(SftpErrorKind::BadMessage, _) => ErrorKind::InvalidInput,
+            // The client folds every status past OP_UNSUPPORTED into Unknown, so
+            // only the server's message tells them apart
+            Error::SftpError(_, message) => status_message_kind(message.get().0),
+            _ if transport_lost => ErrorKind::ConnectionLost,
+            _ => ErrorKind::Other,
+        };
+
+        SftpError {
+            kind,
+            transport_lost,
+            channel_failed: transport_lost,
+            ..SftpError::other(format!("{}: {}", context, error))
+        }
+    }
+
+    // Attach the path the error is about, unless a more specific one is already set
+    pub fn with_path<P: AsRef<str>>(mut self, path: P) -> Self {
+        if self.path.is_none() {
+            self.path = Some(path.as_ref().to_string());
+        }
+        self
+    }
+
+    // Append what else went wrong while handling this error, e.g. a failed reconnect
+    pub fn with_cause(mut self, cause: &SftpError) -> Self {
+        self.message = format!("{} ({})", self.message, cause.message);
+        self
+    }
+
+    // Prefix the message, e.g. with the jump host a connection failed on
+    pub fn context(mut self, context: &str) -> Self {
+        self.message = format!("{}: {}", context, self.message);
+        self
+    }
+}
+
+impl fmt::Display for SftpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for SftpError {}
+
+// Helpers that only produce a message are reported as ErrorKind::Other
+impl From<String> for SftpError {
+    fn from(message: String) -> Self {
+        SftpError::other(message)
+    }
+}
+
+impl From<&str> for SftpError {
+    fn from(message: &str) -> Self {
+        SftpError::other(message)
+    }
+}
+
+// A lookup whose failure doesn't have to fail the operation, e.g. resolving a
+// link that dangles: None, unless the connection broke
+pub fn unless_transport<T>(result: Result<T, SftpError>) -> Result<Option<T>, SftpError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(error) if error.transport_lost => Err(error),
+        Err(_) => Ok(None),
+    }
+}
+
+pub fn is_transport_error(error: &ssh2::Error) -> bool {
+    match error.code() {
+        ErrorCode::Session(code) => TRANSPORT_ERROR_CODES.contains(&code),
+        ErrorCode::SFTP(code) => code == SFTP_NO_CONNECTION || code == SFTP_CONNECTION_LOST,
+    }
+}
+
+fn ssh_error_kind(error: &ssh2::Error) -> ErrorKind {
+    match error.code() {
+        ErrorCode::Session(code) if TIMEOUT_ERROR_CODES.contains(&code) => ErrorKind::Timeout,
+        ErrorCode::Session(code) if TRANSPORT_ERROR_CODES.contains(&code) => {
+            ErrorKind::ConnectionLost
+        }
+        ErrorCode::Session(code) if AUTH_ERROR_CODES.contains(&code) => ErrorKind::AuthFailed,
+        ErrorCode::Session(_) => ErrorKind::Other,
+        ErrorCode::SFTP(code) => match code {
+            SFTP_NO_SUCH_FILE | SFTP_NO_SUCH_PATH => ErrorKind::NotFound,
+            SFTP_PERMISSION_DENIED | SFTP_WRITE_PROTECT => ErrorKind::PermissionDenied,
+            SFTP_FILE_ALREADY_EXISTS => ErrorKind::AlreadyExists,
+            SFTP_DIR_NOT_EMPTY => ErrorKind::NotEmpty,
+            SFTP_NO_SPACE_ON_FILESYSTEM | SFTP_QUOTA_EXCEEDED => ErrorKind::NoSpace,
+            SFTP_NO_CONNECTION | SFTP_CONNECTION_LOST => ErrorKind::ConnectionLost,
+            SFTP_OP_UNSUPPORTED => ErrorKind::Unsupported,
+            _ => ErrorKind::Other,
+        },
+    }
+}
+
+// Kind of an SFTP status by its message, for statuses whose code is lost. Matches
+// the status names of draft-ietf-secsh-filexfer and the strerror texts servers
+// pass on; anything else, including a plain "Failure", is Other.
+fn status_message_kind(message: &str) -> ErrorKind {
+    let message = message.to_ascii_lowercase();
+    let has = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+    if has(&["no such path", "no such file"]) {
+        ErrorKind::NotFound
+    } else if has(&["already exists", "file exists"]) {
+        ErrorKind::AlreadyExists
+    } else if has(&["not empty"]) {
+        ErrorKind::NotEmpty
+    } else if has(&["no space", "quota"]) {
+        ErrorKind::NoSpace
+    } else if has(&[
+        "write protect",
+        "read-only file system",
+        "permission denied",
+    ]) {
+        ErrorKind::PermissionDenied
+    } else if has(&["no connection", "connection lost"]) {
+        ErrorKind::ConnectionLost
+    } else {
+        ErrorKind::Other
+    }
+}
+
+fn io_error_kind(error: &io::Error) -> ErrorKind {
+    match error.kind() {
+        io::ErrorKind::NotFound => ErrorKind::NotFound,
+        io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+        io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+        io::ErrorKind::TimedOut => ErrorKind::Timeout,
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::AddrNotAvailable => {
+            ErrorKind::ConnectionFailed
+        }
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe => ErrorKind::ConnectionLost,
+        io::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+        io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+        _ => ErrorKind::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssh_error(code: ErrorCode) -> SftpError {
+        SftpError::ssh("Failed", ssh2::Error::new(code, "message"))
+    }
+
+    #[test]
+    fn sftp_status_codes_map_to_kinds() {
+        assert_eq!(ssh_error(ErrorCode::SFTP(2)).kind, ErrorKind::NotFound);
+        assert_eq!(
+            ssh_error(ErrorCode::SFTP(3)).kind,
+            ErrorKind::PermissionDenied
+        );
+        // SSH_FX_FAILURE says nothing more specific
+        assert_eq!(ssh_error(ErrorCode::SFTP(4)).kind, ErrorKind::Other);
+
+        let status = ssh_error(ErrorCode::SFTP(2));
+        assert!(!status.transport_lost && !status.channel_failed);
+    }
+
+    #[test]
+    fn session_codes_map_to_kinds() {
+        let timeout = ssh_error(ErrorCode::Session(-9));
+        assert_eq!(timeout.kind, ErrorKind::Timeout);
+        assert!(timeout.transport_lost && timeout.channel_failed);
+
+        let disconnect = ssh_error(ErrorCode::Session(-13));
+        assert_eq!(disconnect.kind, ErrorKind::ConnectionLost);
+        assert!(disconnect.transport_lost);
+
+        assert_eq!(
+            ssh_error(ErrorCode::Session(-18)).kind,
+            ErrorKind::AuthFailed
+        );
+    }
+
+    #[test]
+    fn status_messages_map_to_kinds() {
+        assert_eq!(status_message_kind("No such file"), ErrorKind::NotFound);
+        assert_eq!(
+            status_message_kind("No such file or directory"),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            status_message_kind("Permission denied"),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(status_message_kind("Failure"), ErrorKind::Other);
+    }
+
+    #[test]
+    fn io_errors_map_to_kinds() {
+        let kind = |kind: io::ErrorKind| io_error_kind(&io::Error::from(kind));
+        assert_eq!(kind(io::ErrorKind::NotFound), ErrorKind::NotFound);
+        assert_eq!(
+            kind(io::ErrorKind::PermissionDenied),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(kind(io::ErrorKind::TimedOut), ErrorKind::Timeout);
+        assert_eq!(
+            kind(io::ErrorKind::ConnectionRefused),
+            ErrorKind::ConnectionFailed
+        );
+        assert_eq!(kind(io::ErrorKind::BrokenPipe), ErrorKind::ConnectionLost);
+        assert_eq!(kind(io::ErrorKind::Other), ErrorKind::Other);
+    }
+}
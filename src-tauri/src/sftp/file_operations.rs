@@ -1,118 +1,334 @@
-use crate::types::*;
+use crate::sftp::engine::SftpEngine;
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::sftp::queue::TransferQueue;
+use crate::sftp::reconnect::{
+    current_engine, current_pool, reconnect, reconnect_after, with_engine, with_reconnect,
+};
+use crate::sftp::transfers::{remove_record, resume_offset, save_record};
+use crate::sftp::utils::{is_hidden_path, run_blocking};
+use crate::sftp::walk::{on_ssh2, walk_root, walk_step, SymlinkWalk, WalkDir, WalkStep};
+use crate::types::{
+    ActiveTransfer, QueuedTransfer, SymlinkPolicy, TransferDirection, TransferKind, TransferRecord,
+    TransferState,
+};
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::io::prelude::*;
-use std::path::Path;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc, Mutex,
 };
+use std::time::Duration;
+use tauri::AppHandle;
 use tauri::Emitter;
-use tauri::State;
+use tauri::Manager;
 use tauri::Window;
 use uuid::Uuid;
 
-type ConnectionManagerState = Mutex<ConnectionManager>;
+// How many times a transfer resumes after the connection or its channel dropped
+const MAX_TRANSFER_RESUMES: u32 = 3;
+
+// How often a paused transfer checks whether it was resumed or cancelled
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 // Global transfer cancel manager
-static TRANSFER_CANCEL_MAP: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+pub static TRANSFER_CANCEL_MAP: Lazy<Mutex<HashMap<String, TransferControl>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+// Flags a running transfer is steered by, shared with cancel_transfer and pause_transfer
+#[derive(Clone, Default)]
+pub struct TransferControl {
+    pub cancel_flag: Arc<AtomicBool>,
+    pub pause_flag: Arc<AtomicBool>,
+}
+
+// Upload a local file. With `resume` an interrupted upload continues after what
+// the remote file already holds; `verify` first compares the bytes before that
+// point and starts over when they differ.
 #[tauri::command]
 pub async fn upload_file(
     connection_id: String,
     local_path: String,
     remote_path: String,
-    connections: State<'_, ConnectionManagerState>,
+    resume: Option<bool>,
+    verify: Option<bool>,
     window: Window,
-) -> Result<String, String> {
-    let conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get(&connection_id)
-        .ok_or("Connection not found")?
-        .clone();
-
-    // Generate a unique transfer ID and create a cancel flag
-    let transfer_id = Uuid::new_v4().to_string();
-    let cancel_flag = Arc::new(AtomicBool::new(false));
+) -> Result<String, SftpError> {
+    let record = TransferRecord::new(
+        window.app_handle(),
+        connection_id,
+        TransferDirection::Upload,
+        local_path,
+        remote_path,
+    )?;
+    start_upload(
+        record,
+        resume.unwrap_or(false),
+        verify.unwrap_or(false),
+        window,
+    )
+    .await
+}
+
+// Run the upload of a transfer record under its transfer id. A dropped connection
+// is re-established and the upload continued, like downloads do. The record is
+// kept while the upload runs and after it fails, so resume_transfer can pick it up.
+pub async fn start_upload(
+    record: TransferRecord,
+    resume: bool,
+    verify: bool,
+    window: Window,
+) -> Result<String, SftpError> {
+    let app = window.app_handle().clone();
+    // Fail now if the connection is gone; the engine is taken when the upload starts
+    current_engine(&app, &record.connection_id)?;
+    // Without a record the upload still runs, it just can't be resumed by id
+    save_record(&app, &record).ok();
+
+    let TransferRecord {
+        transfer_id,
+        connection_id,
+        local_path,
+        remote_path,
+        ..
+    } = record.clone();
+
+    // Register the transfer for cancel and pause
+    let control = TransferControl::default();
     TRANSFER_CANCEL_MAP
         .lock()
         .unwrap()
-        .insert(transfer_id.clone(), cancel_flag.clone());
+        .insert(transfer_id.clone(), control.clone());
 
-    // Spawn a new task for the upload
+    // Queue the upload
     let transfer_id_return = transfer_id.clone();
-    tokio::spawn({
-        let transfer_id = transfer_id.clone(); // Move transfer_id into the task
-        async move {
-            let sftp = session
-                .sftp()
-                .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-            let mut local_file = std::fs::File::open(&local_path)
-                .map_err(|e| format!("Failed to open local file: {}", e))?;
-
-            let total_size = local_file
-                .metadata()
-                .map_err(|e| format!("Failed to stat local file: {}", e))?
-                .len();
-
-            let mut remote_file = sftp
-                .create(Path::new(&remote_path))
-                .map_err(|e| format!("Failed to create remote file: {}", e))?;
-
-            let mut buffer = [0u8; 8192];
-            let mut transferred = 0u64;
-            let mut cancelled = false;
-
-            loop {
-                let n = local_file
-                    .read(&mut buffer)
-                    .map_err(|e| format!("Read error: {}", e))?;
-                if n == 0 {
-                    break;
-                }
-                remote_file
-                    .write_all(&buffer[..n])
-                    .map_err(|e| format!("Write error: {}", e))?;
-                transferred += n as u64;
-
-                // Emit progress event
-                window
-                    .emit(
-                        "upload_progress",
-                        serde_json::json!({
-                            "connection_id": connection_id,
-                            "path": remote_path,
-                            "transferred": transferred,
-                            "total": total_size,
-                            "type": "upload",
-                            "transfer_id": transfer_id
-                        }),
-                    )
-                    .ok();
-
-                // Check for cancellation
-                if cancel_flag.load(Ordering::Relaxed) {
+    let transfer = QueuedTransfer::new(
+        &transfer_id,
+        &connection_id,
+        TransferKind::Upload,
+        &remote_path,
+    );
+    app.state::<TransferQueue>()
+        .enqueue(&app, transfer, control.clone(), {
+            let app = app.clone();
+            let context = TransferContext {
+                window: window.clone(),
+                connection_id: connection_id.clone(),
+                transfer_id: transfer_id.clone(),
+                cancel_flag: control.cancel_flag,
+                pause_flag: control.pause_flag,
+                tree: None,
+            };
+            async move {
+                let (mut engine, mut generation) = current_engine(&app, &connection_id)?;
+                let written = if resume {
+                    resume_offset(&engine, &record, verify).await?
+                } else {
+                    0
+                };
+
+                let result = upload_resuming(
+                    &app,
+                    &mut engine,
+                    &mut generation,
+                    &local_path,
+                    &remote_path,
+                    written,
+                    &context,
+                )
+                .await;
+
+                // After transfer loop (success or error)
+                TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+
+                let cancelled = match result {
+                    Ok(cancelled) => cancelled,
+                    // The connection is healed for the next command
+                    Err(error) => {
+                        return Err(reconnect_after(&app, &connection_id, generation, error).await)
+                    }
+                };
+                remove_record(&app, &transfer_id).ok();
+
+                // Emit process_finished event if not cancelled
+                if !cancelled {
                     window
                         .emit(
-                            "transfer_cancelled",
+                            "process_finished",
                             serde_json::json!({
-                                "transfer_id": transfer_id,
-                                "type": "upload"
+                                "connection_id": connection_id,
+                                "path": remote_path,
+                                "type": "upload",
+                                "transfer_id": transfer_id
                             }),
                         )
                         .ok();
-                    cancelled = true;
-                    break;
                 }
+
+                Ok::<(), SftpError>(())
             }
+        });
 
-            // After transfer loop (success or error)
-            TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+    Ok(transfer_id_return)
+}
+
+// Upload one file from byte `written` on. A dropped connection is re-established
+// and the upload continued after the bytes the server acknowledged; `engine` and
+// `generation` follow the reconnect. Returns whether the upload was cancelled.
+async fn upload_resuming(
+    app: &AppHandle,
+    engine: &mut SftpEngine,
+    generation: &mut u64,
+    local_path: &str,
+    remote_path: &str,
+    mut written: u64,
+    context: &TransferContext,
+) -> Result<bool, SftpError> {
+    let mut resumes = 0;
+
+    loop {
+        let (confirmed, result) = engine
+            .upload(local_path, remote_path, written, context)
+            .await?;
+        written = confirmed;
+
+        match result {
+            Err(error) if error.transport_lost && resumes < MAX_TRANSFER_RESUMES => {
+                // e.g. when the channel closed while the upload was paused
+                resumes += 1;
+                reconnect(app, &context.connection_id, *generation).await?;
+                let (new_engine, new_generation) = current_engine(app, &context.connection_id)?;
+                *engine = new_engine;
+                *generation = new_generation;
+            }
+            Err(error) if error.channel_failed && resumes < MAX_TRANSFER_RESUMES => {
+                // Only the channel broke: the pool opens a new one on the
+                // same session, so there is nothing to reconnect
+                resumes += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Upload a local directory and everything below it to `remote_path`, creating
+// the remote directories as needed. All files go under one transfer id;
+// upload_progress carries the totals of the whole tree alongside each file's.
+// Files and directories that fail don't stop the rest; they are listed in
+// process_finished.
+#[tauri::command]
+pub async fn upload_directory(
+    connection_id: String,
+    local_path: String,
+    remote_path: String,
+    window: Window,
+) -> Result<String, SftpError> {
+    let app = window.app_handle().clone();
+    // Fail now if the connection is gone; the engine is taken when the upload starts
+    current_engine(&app, &connection_id)?;
+
+    let tree = run_blocking({
+        let local_path = local_path.clone();
+        move || local_tree(Path::new(&local_path))
+    })
+    .await?
+    .map_err(|e| SftpError::local("Failed to read local directory", e).with_path(&local_path))?;
+
+    // Generate a unique transfer ID and register it for cancel and pause
+    let transfer_id = Uuid::new_v4().to_string();
+    let control = TransferControl::default();
+    TRANSFER_CANCEL_MAP
+        .lock()
+        .unwrap()
+        .insert(transfer_id.clone(), control.clone());
+
+    // Queue the upload
+    let transfer_id_return = transfer_id.clone();
+    let transfer = QueuedTransfer::new(
+        &transfer_id,
+        &connection_id,
+        TransferKind::Upload,
+        &remote_path,
+    );
+    app.state::<TransferQueue>()
+        .enqueue(&app, transfer, control.clone(), {
+            let app = app.clone();
+            let total_bytes = tree.files.iter().map(|(_, size)| size).sum();
+            let context = TransferContext {
+                window: window.clone(),
+                connection_id: connection_id.clone(),
+                transfer_id: transfer_id.clone(),
+                cancel_flag: control.cancel_flag,
+                pause_flag: control.pause_flag,
+                tree: Some(Arc::new(TreeProgress::new(
+                    total_bytes,
+                    tree.files.len() as u64,
+                ))),
+            };
+            async move {
+                let (mut engine, mut generation) = current_engine(&app, &connection_id)?;
+                let result = async {
+                    let mut summary = TreeSummary::default();
+                    // Directories that couldn't be created; nothing below them is uploaded
+                    let mut failed: Vec<&PathBuf> = Vec::new();
+                    let under_failed = |path: &Path, failed: &[&PathBuf]| {
+                        failed.iter().any(|dir| path.starts_with(dir))
+                    };
+
+                    // Parents come before their children, so each mkdir has its parent
+                    for directory in &tree.directories {
+                        if under_failed(directory, &failed) {
+                            continue;
+                        }
+                        let remote_dir = remote_child(&remote_path, directory);
+                        if let Err(error) = engine.ensure_dir(&remote_dir).await {
+                            summary.fail(error.with_path(&remote_dir))?;
+                            failed.push(directory);
+                        }
+                    }
+
+                    for (file, size) in &tree.files {
+                        if !under_failed(file, &failed) {
+                            let local_file = Path::new(&local_path).join(file);
+                            let uploaded = upload_resuming(
+                                &app,
+                                &mut engine,
+                                &mut generation,
+                                &local_file.to_string_lossy(),
+                                &remote_child(&remote_path, file),
+                                0,
+                                &context,
+                            )
+                            .await;
+                            match uploaded {
+                                Ok(true) => return Ok(None),
+                                Ok(false) => {}
+                                Err(error) => summary.fail(error)?,
+                            }
+                        }
+                        if let Some(tree) = &context.tree {
+                            tree.file_completed(*size);
+                        }
+                    }
+                    Ok::<_, SftpError>(Some(summary))
+                }
+                .await;
+
+                // After transfer loop (success or error)
+                TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+
+                let summary = match result {
+                    Ok(Some(summary)) => summary,
+                    // Cancelled
+                    Ok(None) => return Ok(()),
+                    // The connection is healed for the next command
+                    Err(error) => {
+                        return Err(reconnect_after(&app, &connection_id, generation, error).await)
+                    }
+                };
 
-            // Emit process_finished event if not cancelled
-            if !cancelled {
                 window
                     .emit(
                         "process_finished",
@@ -120,117 +336,312 @@ pub async fn upload_file(
                             "connection_id": connection_id,
                             "path": remote_path,
                             "type": "upload",
-                            "transfer_id": transfer_id
+                            "transfer_id": transfer_id,
+                            "files": tree.files.len(),
+                            "failures": summary.failures
                         }),
                     )
                     .ok();
+
+                Ok::<(), SftpError>(())
             }
+        });
 
-            Ok::<(), String>(())
+    Ok(transfer_id_return)
+}
+
+// Contents of a local directory, with paths relative to it. The root itself is
+// the first directory; parents always come before their children.
+struct LocalTree {
+    directories: Vec<PathBuf>,
+    files: Vec<(PathBuf, u64)>, // With their sizes
+}
+
+// Links to files are uploaded as the file they point at. Links to directories
+// are left out, so a link cycle can't make the walk endless.
+fn local_tree(root: &Path) -> std::io::Result<LocalTree> {
+    let mut tree = LocalTree {
+        directories: vec![PathBuf::new()],
+        files: Vec::new(),
+    };
+
+    let mut index = 0;
+    while index < tree.directories.len() {
+        let directory = tree.directories[index].clone();
+        index += 1;
+
+        for entry in std::fs::read_dir(root.join(&directory))? {
+            let entry = entry?;
+            let relative = directory.join(entry.file_name());
+            let metadata = if entry.file_type()?.is_symlink() {
+                match std::fs::metadata(entry.path()) {
+                    Ok(metadata) if metadata.is_file() => metadata,
+                    _ => continue,
+                }
+            } else {
+                entry.metadata()?
+            };
+
+            if metadata.is_dir() {
+                tree.directories.push(relative);
+            } else if metadata.is_file() {
+                tree.files.push((relative, metadata.len()));
+            }
         }
-    });
+    }
 
-    Ok(transfer_id_return)
+    Ok(tree)
+}
+
+// Remote path of a path relative to `root`, with '/' whatever the local separator
+fn remote_child(root: &str, relative: &Path) -> String {
+    let mut path = root.trim_end_matches('/').to_string();
+    for component in relative.components() {
+        path.push('/');
+        path.push_str(&component.as_os_str().to_string_lossy());
+    }
+    path
 }
 
+// Download a remote file. With `resume` an interrupted download continues after
+// what the local file already holds; `verify` first compares the bytes before
+// that point and starts over when they differ.
 #[tauri::command]
 pub async fn download_file(
     connection_id: String,
     remote_path: String,
     local_path: String,
-    connections: State<'_, ConnectionManagerState>,
+    resume: Option<bool>,
+    verify: Option<bool>,
     window: Window,
-) -> Result<String, String> {
-    let conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get(&connection_id)
-        .ok_or("Connection not found")?
-        .clone();
-
-    // Generate a unique transfer ID and create a cancel flag
-    let transfer_id = Uuid::new_v4().to_string();
-    let cancel_flag = Arc::new(AtomicBool::new(false));
+) -> Result<String, SftpError> {
+    let record = TransferRecord::new(
+        window.app_handle(),
+        connection_id,
+        TransferDirection::Download,
+        local_path,
+        remote_path,
+    )?;
+    start_download(
+        record,
+        resume.unwrap_or(false),
+        verify.unwrap_or(false),
+        window,
+    )
+    .await
+}
+
+// Run the download of a transfer record under its transfer id. The record is
+// kept while the download runs and after it fails, so resume_transfer can pick it up.
+pub async fn start_download(
+    record: TransferRecord,
+    resume: bool,
+    verify: bool,
+    window: Window,
+) -> Result<String, SftpError> {
+    let app = window.app_handle().clone();
+    // Fail now if the connection is gone; the engine is taken when the download starts
+    current_engine(&app, &record.connection_id)?;
+    // Without a record the download still runs, it just can't be resumed by id
+    save_record(&app, &record).ok();
+
+    let TransferRecord {
+        transfer_id,
+        connection_id,
+        remote_path,
+        ..
+    } = record.clone();
+
+    // Register the transfer for cancel and pause
+    let control = TransferControl::default();
     TRANSFER_CANCEL_MAP
         .lock()
         .unwrap()
-        .insert(transfer_id.clone(), cancel_flag.clone());
+        .insert(transfer_id.clone(), control.clone());
 
-    // Spawn a new task for the download
+    // Queue the download
     let transfer_id_return = transfer_id.clone();
-    tokio::spawn({
-        let transfer_id = transfer_id.clone(); // Move transfer_id into the task
-        async move {
-            let sftp = session
-                .sftp()
-                .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-            // Open remote file
-            let mut remote_file = sftp
-                .open(Path::new(&remote_path))
-                .map_err(|e| format!("Failed to open remote file: {}", e))?;
-
-            // Get file size for progress
-            let stat = sftp
-                .stat(Path::new(&remote_path))
-                .map_err(|e| format!("Failed to stat remote file: {}", e))?;
-            let total_size = stat.size.unwrap_or(0);
-
-            // Create local file
-            let mut local_file = std::fs::File::create(&local_path)
-                .map_err(|e| format!("Failed to create local file: {}", e))?;
-
-            // Copy in chunks and emit progress
-            let mut buffer = [0u8; 8192];
-            let mut transferred = 0u64;
-            let mut cancelled = false;
-            loop {
-                let n = remote_file
-                    .read(&mut buffer)
-                    .map_err(|e| format!("Read error: {}", e))?;
-                if n == 0 {
-                    break;
+    let transfer = QueuedTransfer::new(
+        &transfer_id,
+        &connection_id,
+        TransferKind::Download,
+        &remote_path,
+    );
+    app.state::<TransferQueue>()
+        .enqueue(&app, transfer, control.clone(), {
+            let app = app.clone();
+            let context = TransferContext {
+                window: window.clone(),
+                connection_id: connection_id.clone(),
+                transfer_id: transfer_id.clone(),
+                cancel_flag: control.cancel_flag,
+                pause_flag: control.pause_flag,
+                tree: None,
+            };
+            async move {
+                let (mut engine, mut generation) = current_engine(&app, &connection_id)?;
+                let start = if resume {
+                    resume_offset(&engine, &record, verify).await?
+                } else {
+                    0
+                };
+
+                // Create local file, or open the partial one and continue at its end
+                let local_file = if start > 0 {
+                    std::fs::OpenOptions::new()
+                        .write(true)
+                        .open(&record.local_path)
+                        .and_then(|mut file| {
+                            file.seek(SeekFrom::Start(start))?;
+                            Ok(file)
+                        })
+                } else {
+                    std::fs::File::create(&record.local_path)
                 }
-                local_file
-                    .write_all(&buffer[..n])
-                    .map_err(|e| format!("Write error: {}", e))?;
-                transferred += n as u64;
-
-                // Emit progress event to frontend
-                window
-                    .emit(
-                        "download_progress",
-                        serde_json::json!({
-                            "connection_id": connection_id,
-                            "path": remote_path,
-                            "transferred": transferred,
-                            "total": total_size,
-                            "type": "download",
-                            "transfer_id": transfer_id
-                        }),
-                    )
-                    .ok();
-
-                // Check for cancellation
-                if cancel_flag.load(Ordering::Relaxed) {
+                .map_err(|e| {
+                    SftpError::local("Failed to create local file", e).with_path(&record.local_path)
+                })?;
+
+                let mut progress = DownloadProgress {
+                    local_file,
+                    local_path: record.local_path.clone(),
+                    transferred: start,
+                };
+                let mut resumes = 0;
+
+                let result = loop {
+                    let (returned, result) =
+                        engine.download(&remote_path, progress, &context).await?;
+                    progress = returned;
+
+                    match result {
+                        Err(error) if error.transport_lost && resumes < MAX_TRANSFER_RESUMES => {
+                            // Reconnect and continue from the bytes already written locally
+                            resumes += 1;
+                            reconnect(&app, &connection_id, generation).await?;
+                            let (new_engine, new_generation) =
+                                current_engine(&app, &connection_id)?;
+                            engine = new_engine;
+                            generation = new_generation;
+                        }
+                        Err(error) if error.channel_failed && resumes < MAX_TRANSFER_RESUMES => {
+                            // Continue on a new channel of the same session
+                            resumes += 1;
+                        }
+                        result => break result,
+                    }
+                };
+
+                // After transfer loop (success or error)
+                TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+
+                let cancelled = match result {
+                    Ok(cancelled) => cancelled,
+                    // The connection is healed for the next command
+                    Err(error) => {
+                        return Err(reconnect_after(&app, &connection_id, generation, error).await)
+                    }
+                };
+                remove_record(&app, &transfer_id).ok();
+
+                // Emit process_finished event if not cancelled
+                if !cancelled {
                     window
                         .emit(
-                            "transfer_cancelled",
+                            "process_finished",
                             serde_json::json!({
-                                "transfer_id": transfer_id,
-                                "type": "download"
+                                "connection_id": connection_id,
+                                "path": remote_path,
+                                "type": "download",
+                                "transfer_id": transfer_id
                             }),
                         )
                         .ok();
-                    cancelled = true;
-                    break;
                 }
+
+                Ok::<(), SftpError>(())
             }
+        });
+
+    Ok(transfer_id_return)
+}
+
+// Download a remote directory and everything below it into `local_path`. The
+// tree is sized first so download_progress can carry its totals; symlinks and
+// special files are skipped, and entries that fail don't stop the rest. Both
+// are listed in process_finished.
+#[tauri::command]
+pub async fn download_directory(
+    connection_id: String,
+    remote_path: String,
+    local_path: String,
+    window: Window,
+) -> Result<String, SftpError> {
+    let app = window.app_handle().clone();
+    // Fail now if the connection is gone; the engine is taken when the download starts
+    current_engine(&app, &connection_id)?;
+
+    // Generate a unique transfer ID and register it for cancel and pause
+    let transfer_id = Uuid::new_v4().to_string();
+    let control = TransferControl::default();
+    TRANSFER_CANCEL_MAP
+        .lock()
+        .unwrap()
+        .insert(transfer_id.clone(), control.clone());
+
+    // Queue the download
+    let transfer_id_return = transfer_id.clone();
+    let transfer = QueuedTransfer::new(
+        &transfer_id,
+        &connection_id,
+        TransferKind::Download,
+        &remote_path,
+    );
+    app.state::<TransferQueue>()
+        .enqueue(&app, transfer, control.clone(), {
+            let app = app.clone();
+            async move {
+                let (engine, generation) = current_engine(&app, &connection_id)?;
+                let mut context = TransferContext {
+                    window: window.clone(),
+                    connection_id: connection_id.clone(),
+                    transfer_id: transfer_id.clone(),
+                    cancel_flag: control.cancel_flag,
+                    pause_flag: control.pause_flag,
+                    tree: None,
+                };
+                let result = async {
+                    let sized = engine
+                        .directory_size(
+                            &remote_path,
+                            SymlinkPolicy::Skip,
+                            context.cancel_flag.clone(),
+                            &window,
+                            &transfer_id,
+                        )
+                        .await;
+                    let (total_bytes, total_files) = match sized {
+                        Ok(totals) => totals,
+                        // Cancelled while sizing the tree
+                        Err(_) if context.cancelled("download") => return Ok(None),
+                        Err(error) => return Err(error),
+                    };
+                    context.tree = Some(Arc::new(TreeProgress::new(total_bytes, total_files)));
+                    download_tree(&engine, &remote_path, Path::new(&local_path), &context).await
+                }
+                .await;
 
-            // After transfer loop (success or error)
-            TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+                // After transfer loop (success or error)
+                TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+
+                let summary = match result {
+                    Ok(Some(summary)) => summary,
+                    Ok(None) => return Ok(()),
+                    Err(error) => {
+                        return Err(reconnect_after(&app, &connection_id, generation, error).await)
+                    }
+                };
 
-            // Emit process_finished event if not cancelled
-            if !cancelled {
                 window
                     .emit(
                         "process_finished",
@@ -238,81 +649,371 @@ pub async fn download_file(
                             "connection_id": connection_id,
                             "path": remote_path,
                             "type": "download",
-                            "transfer_id": transfer_id
+                            "transfer_id": transfer_id,
+                            "failures": summary.failures,
+                            "skipped": summary.skipped
                         }),
                     )
                     .ok();
+
+                Ok::<(), SftpError>(())
             }
+        });
 
-            Ok::<(), String>(())
+    Ok(transfer_id_return)
+}
+
+// What a directory transfer left out
+#[derive(Default)]
+struct TreeSummary {
+    failures: Vec<SftpError>,
+    skipped: Vec<String>, // Remote paths of symlinks and special files a download skips
+}
+
+impl TreeSummary {
+    // Record a failure and go on, unless the connection is gone
+    fn fail(&mut self, error: SftpError) -> Result<(), SftpError> {
+        if error.transport_lost {
+            return Err(error);
         }
-    });
+        self.failures.push(error);
+        Ok(())
+    }
+}
+
+// Mirror a remote tree into a local directory, one directory at a time.
+// Returns None when cancelled. Only cancellation and a lost connection stop
+// the download; other failures are collected in the summary.
+async fn download_tree(
+    engine: &SftpEngine,
+    remote_root: &str,
+    local_root: &Path,
+    context: &TransferContext,
+) -> Result<Option<TreeSummary>, SftpError> {
+    let mut summary = TreeSummary::default();
+    let mut directories = vec![(remote_root.to_string(), local_root.to_path_buf())];
+
+    while let Some((remote_dir, local_dir)) = directories.pop() {
+        if let Err(e) = std::fs::create_dir_all(&local_dir) {
+            let error = SftpError::local("Failed to create local directory", e);
+            summary.fail(error.with_path(local_dir.to_string_lossy()))?;
+            continue;
+        }
+
+        let entries = match engine.read_dir(&remote_dir).await {
+            Ok(entries) => entries,
+            Err(error) => {
+                summary.fail(error)?;
+                continue;
+            }
+        };
+
+        for (remote_file, stat) in entries {
+            let remote_file = remote_file.to_string_lossy().replace("\\", "/");
+            let local_file =
+                local_dir.join(Path::new(&remote_file).file_name().unwrap_or_default());
 
-    Ok((transfer_id_return))
+            if stat.file_type().is_symlink() {
+                summary.skipped.push(remote_file);
+                continue;
+            }
+            if stat.is_dir() {
+                directories.push((remote_file, local_file));
+                continue;
+            }
+
+            let size = stat.size.unwrap_or(0);
+            let completed = |context: &TransferContext| {
+                if let Some(tree) = &context.tree {
+                    tree.file_completed(size);
+                }
+            };
+            if !stat.file_type().is_file() {
+                summary.skipped.push(remote_file);
+                completed(context);
+                continue;
+            }
+
+            let file = match std::fs::File::create(&local_file) {
+                Ok(file) => file,
+                Err(e) => {
+                    let error = SftpError::local("Failed to create local file", e);
+                    summary.fail(error.with_path(local_file.to_string_lossy()))?;
+                    completed(context);
+                    continue;
+                }
+            };
+            let progress = DownloadProgress {
+                local_file: file,
+                local_path: local_file.to_string_lossy().to_string(),
+                transferred: 0,
+            };
+            match engine.download(&remote_file, progress, context).await?.1 {
+                Ok(true) => return Ok(None),
+                Ok(false) => {}
+                Err(error) => summary.fail(error)?,
+            }
+            completed(context);
+        }
+    }
+
+    Ok(Some(summary))
 }
 
-#[tauri::command]
-pub async fn delete_item(
-    connection_id: String,
-    path: String,
-    is_directory: bool,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<(), String> {
-    let mut conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get_mut(&connection_id)
-        .ok_or("Connection not found")?;
+// Where a transfer reports its progress and how it learns it was cancelled
+#[derive(Clone)]
+pub struct TransferContext {
+    pub window: Window,
+    pub connection_id: String,
+    pub transfer_id: String,
+    pub cancel_flag: Arc<AtomicBool>,
+    pub pause_flag: Arc<AtomicBool>,
+    pub tree: Option<Arc<TreeProgress>>, // Set when the file is part of a directory transfer
+}
 
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
+impl TransferContext {
+    // Emit upload_progress / download_progress for `kind` "upload" or "download".
+    // Within a directory transfer the totals of the whole tree are included.
+    pub fn emit_progress(&self, kind: &str, path: &str, transferred: u64, total: u64) {
+        let mut payload = serde_json::json!({
+            "connection_id": self.connection_id,
+            "path": path,
+            "transferred": transferred,
+            "total": total,
+            "type": kind,
+            "transfer_id": self.transfer_id
+        });
+        if let Some(tree) = &self.tree {
+            payload["tree_transferred"] =
+                (tree.completed_bytes.load(Ordering::Relaxed) + transferred).into();
+            payload["tree_total"] = tree.total_bytes.into();
+            payload["files_completed"] = tree.completed_files.load(Ordering::Relaxed).into();
+            payload["files_total"] = tree.total_files.into();
+        }
 
-    if is_directory {
-        // Use recursive deletion for directories
-        delete_directory_recursive_helper(&sftp, &path)?;
-    } else {
-        sftp.unlink(Path::new(&path))
-            .map_err(|e| format!("Failed to delete file: {}", e))?;
+        self.window
+            .emit(&format!("{}_progress", kind), payload)
+            .ok();
     }
 
-    Ok(())
+    // Whether the transfer should hold still; a cancelled transfer never is
+    pub fn paused(&self) -> bool {
+        self.pause_flag.load(Ordering::Relaxed) && !self.cancel_flag.load(Ordering::Relaxed)
+    }
+
+    // Wait until the transfer is resumed or cancelled
+    pub async fn wait_while_paused(&self) {
+        while self.paused() {
+            tokio::time::sleep(PAUSE_POLL_INTERVAL).await;
+        }
+    }
+
+    // wait_while_paused for transfers running on the blocking pool
+    pub fn wait_while_paused_blocking(&self) {
+        while self.paused() {
+            std::thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+    }
+
+    // Check for cancellation, emitting transfer_cancelled when it was requested
+    pub fn cancelled(&self, kind: &str) -> bool {
+        if !self.cancel_flag.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        self.window
+            .emit(
+                "transfer_cancelled",
+                serde_json::json!({
+                    "transfer_id": self.transfer_id,
+                    "type": kind
+                }),
+            )
+            .ok();
+        true
+    }
 }
 
-// Helper function for recursive directory deletion
-fn delete_directory_recursive_helper(sftp: &ssh2::Sftp, path: &str) -> Result<(), String> {
-    let dir_path = Path::new(path);
+// Totals of a transfer that moves a whole directory tree under one transfer id
+pub struct TreeProgress {
+    pub total_bytes: u64,
+    pub total_files: u64,
+    completed_bytes: AtomicU64, // Bytes of the files finished so far
+    completed_files: AtomicU64,
+}
 
-    // List directory contents
-    let entries = sftp
-        .readdir(dir_path)
-        .map_err(|e| format!("Failed to read directory {}: {}", path, e))?;
+impl TreeProgress {
+    pub fn new(total_bytes: u64, total_files: u64) -> Self {
+        TreeProgress {
+            total_bytes,
+            total_files,
+            completed_bytes: AtomicU64::new(0),
+            completed_files: AtomicU64::new(0),
+        }
+    }
 
-    // Delete all contents first
-    for (file_path, stat) in entries {
-        let file_path_str = file_path.to_string_lossy().replace("\\", "/");
+    pub fn file_completed(&self, size: u64) {
+        self.completed_bytes.fetch_add(size, Ordering::Relaxed);
+        self.completed_files.fetch_add(1, Ordering::Relaxed);
+    }
+}
 
-        // Skip . and .. entries
-        let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+// Write a local file from byte `*written` on to the remote path over ssh2,
+// advancing `written` and emitting upload_progress per chunk. Returns whether the
+// transfer was cancelled.
+pub fn upload_blocking(
+    sftp: &ssh2::Sftp,
+    session: &ssh2::Session,
+    local_path: &str,
+    remote_path: &str,
+    written: &mut u64,
+    context: &TransferContext,
+) -> Result<bool, SftpError> {
+    let start = *written;
+    let mut local_file = std::fs::File::open(local_path)
+        .map_err(|e| SftpError::local("Failed to open local file", e).with_path(local_path))?;
+
+    let total_size = local_file
+        .metadata()
+        .map_err(|e| SftpError::local("Failed to stat local file", e).with_path(local_path))?
+        .len();
+
+    let mut remote_file = if start > 0 {
+        // Keep what an earlier attempt wrote and continue after it
+        let flags = ssh2::OpenFlags::WRITE | ssh2::OpenFlags::CREATE;
+        let mut remote_file = sftp
+            .open_mode(Path::new(remote_path), flags, 0o644, ssh2::OpenType::File)
+            .map_err(|e| SftpError::ssh("Failed to open remote file", e).with_path(remote_path))?;
+        remote_file.seek(SeekFrom::Start(start)).map_err(|e| {
+            SftpError::io("Failed to resume upload", e, session).with_path(remote_path)
+        })?;
+        local_file
+            .seek(SeekFrom::Start(start))
+            .map_err(|e| SftpError::local("Failed to resume upload", e).with_path(local_path))?;
+        remote_file
+    } else {
+        sftp.create(Path::new(remote_path))
+            .map_err(|e| SftpError::ssh("Failed to create remote file", e).with_path(remote_path))?
+    };
 
-        if name == "." || name == ".." {
-            continue;
+    let mut buffer = [0u8; 8192];
+    let mut transferred = start;
+
+    loop {
+        // A paused upload keeps its handles open and continues where it stopped
+        context.wait_while_paused_blocking();
+        if context.cancelled("upload") {
+            return Ok(true);
         }
 
-        if stat.is_dir() {
-            // Recursively delete subdirectory
-            delete_directory_recursive_helper(sftp, &file_path_str)?;
-        } else {
-            // Delete file
-            sftp.unlink(&file_path)
-                .map_err(|e| format!("Failed to delete file {}: {}", file_path_str, e))?;
+        let n = local_file
+            .read(&mut buffer)
+            .map_err(|e| SftpError::local("Read error", e).with_path(local_path))?;
+        if n == 0 {
+            break;
+        }
+        remote_file
+            .write_all(&buffer[..n])
+            .map_err(|e| SftpError::io("Write error", e, session).with_path(remote_path))?;
+        transferred += n as u64;
+        *written = transferred;
+
+        context.emit_progress("upload", remote_path, transferred, total_size);
+        if context.cancelled("upload") {
+            return Ok(true);
         }
     }
 
-    // Now delete the empty directory
-    sftp.rmdir(dir_path)
-        .map_err(|e| format!("Failed to delete directory {}: {}", path, e))?;
+    Ok(false)
+}
+
+// Local side of a download, carried across reconnects
+pub struct DownloadProgress {
+    pub local_file: std::fs::File,
+    pub local_path: String, // For errors of the local file
+    pub transferred: u64,
+}
 
-    Ok(())
+// Read the remote file from `progress.transferred` onwards into the local file
+// over ssh2, emitting download_progress per chunk. Returns whether the transfer
+// was cancelled.
+pub fn download_blocking(
+    sftp: &ssh2::Sftp,
+    session: &ssh2::Session,
+    remote_path: &str,
+    progress: &mut DownloadProgress,
+    context: &TransferContext,
+) -> Result<bool, SftpError> {
+    // Open remote file
+    let mut remote_file = sftp
+        .open(Path::new(remote_path))
+        .map_err(|e| SftpError::ssh("Failed to open remote file", e).with_path(remote_path))?;
+
+    // Get file size for progress
+    let stat = sftp
+        .stat(Path::new(remote_path))
+        .map_err(|e| SftpError::ssh("Failed to stat remote file", e).with_path(remote_path))?;
+    let total_size = stat.size.unwrap_or(0);
+
+    if progress.transferred > 0 {
+        remote_file
+            .seek(SeekFrom::Start(progress.transferred))
+            .map_err(|e| {
+                SftpError::io("Failed to resume download", e, session).with_path(remote_path)
+            })?;
+    }
+
+    // Copy in chunks and emit progress
+    let mut buffer = [0u8; 8192];
+    loop {
+        // A paused download keeps its handles open and continues where it stopped
+        context.wait_while_paused_blocking();
+        if context.cancelled("download") {
+            return Ok(true);
+        }
+
+        let n = remote_file
+            .read(&mut buffer)
+            .map_err(|e| SftpError::io("Read error", e, session).with_path(remote_path))?;
+        if n == 0 {
+            break;
+        }
+        progress
+            .local_file
+            .write_all(&buffer[..n])
+            .map_err(|e| SftpError::local("Write error", e).with_path(&progress.local_path))?;
+        progress.transferred += n as u64;
+
+        context.emit_progress("download", remote_path, progress.transferred, total_size);
+        if context.cancelled("download") {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[tauri::command]
+pub async fn delete_item(
+    connection_id: String,
+    path: String,
+    is_directory: bool,
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    if is_directory {
+        // Use recursive deletion for directories
+        let (path, app_ref) = (&path, &app);
+        return with_engine(&app, &connection_id, false, |engine| async move {
+            engine.remove_dir_all(path, app_ref).await
+        })
+        .await;
+    }
+
+    with_reconnect(&app, &connection_id, false, move |sftp| {
+        sftp.unlink(Path::new(&path))
+            .map_err(|e| SftpError::ssh("Failed to delete file", e).with_path(&path))
+    })
+    .await
 }
 
 #[tauri::command]
@@ -320,44 +1021,91 @@ pub async fn rename_item(
     connection_id: String,
     old_path: String,
     new_path: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<(), String> {
-    let mut conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get_mut(&connection_id)
-        .ok_or("Connection not found")?;
-
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-    sftp.rename(Path::new(&old_path), Path::new(&new_path), None)
-        .map_err(|e| format!("Failed to rename item: {}", e))?;
-
-    Ok(())
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    with_reconnect(&app, &connection_id, false, move |sftp| {
+        sftp.rename(Path::new(&old_path), Path::new(&new_path), None)
+            .map_err(|e| SftpError::ssh("Failed to rename item", e).with_path(&old_path))
+    })
+    .await
 }
 
 // Cancel transfer
 #[tauri::command]
-pub async fn cancel_transfer(transfer_id: String) -> Result<(), String> {
+pub async fn cancel_transfer(transfer_id: String, app: AppHandle) -> Result<(), SftpError> {
+    // A transfer still waiting in the queue is dropped without starting
+    if app
+        .state::<TransferQueue>()
+        .cancel_queued(&app, &transfer_id)
+    {
+        return Ok(());
+    }
+
     let cancel_map = TRANSFER_CANCEL_MAP.lock().unwrap();
 
-    if let Some(cancel_flag) = cancel_map.get(&transfer_id) {
-        cancel_flag.store(true, Ordering::Relaxed);
+    if let Some(control) = cancel_map.get(&transfer_id) {
+        control.cancel_flag.store(true, Ordering::Relaxed);
         Ok(())
     } else {
-        Err(format!(
-            "Transfer with ID {} not found or already completed",
-            transfer_id
+        Err(SftpError::new(
+            ErrorKind::NotFound,
+            format!(
+                "Transfer with ID {} not found or already completed",
+                transfer_id
+            ),
         ))
     }
 }
 
-// Get list of active transfers
+// Pause a running transfer. It stops once the requests in flight are done and
+// keeps its handles and offset until resume_transfer or cancel_transfer.
 #[tauri::command]
-pub async fn get_active_transfers() -> Result<Vec<String>, String> {
+pub async fn pause_transfer(transfer_id: String, window: Window) -> Result<(), SftpError> {
+    let control = TRANSFER_CANCEL_MAP
+        .lock()
+        .unwrap()
+        .get(&transfer_id)
+        .cloned()
+        .ok_or_else(|| {
+            SftpError::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Transfer with ID {} not found or already completed",
+                    transfer_id
+                ),
+            )
+        })?;
+
+    if !control.pause_flag.swap(true, Ordering::Relaxed) {
+        window
+            .emit(
+                "transfer_paused",
+                serde_json::json!({ "transfer_id": transfer_id }),
+            )
+            .ok();
+        window
+            .app_handle()
+            .state::<TransferQueue>()
+            .emit_changed(window.app_handle());
+    }
+    Ok(())
+}
+
+// Get list of active transfers and whether each is running or paused
+#[tauri::command]
+pub async fn get_active_transfers() -> Result<Vec<ActiveTransfer>, SftpError> {
     let cancel_map = TRANSFER_CANCEL_MAP.lock().unwrap();
-    Ok(cancel_map.keys().cloned().collect())
+    Ok(cancel_map
+        .iter()
+        .map(|(transfer_id, control)| ActiveTransfer {
+            transfer_id: transfer_id.clone(),
+            state: if control.pause_flag.load(Ordering::Relaxed) {
+                TransferState::Paused
+            } else {
+                TransferState::Running
+            },
+        })
+        .collect())
 }
 
 #[tauri::command]
@@ -366,93 +1114,116 @@ pub async fn copy_item(
     source_path: String,
     dest_path: String,
     is_directory: bool,
-    connections: State<'_, ConnectionManagerState>,
+    symlinks: Option<SymlinkPolicy>,
     window: Window,
-) -> Result<String, String> {
-    let conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get(&connection_id)
-        .ok_or("Connection not found")?;
-
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-    // Generate a unique transfer ID and create a cancel flag
+) -> Result<String, SftpError> {
+    // Links inside a copied directory are recreated as links unless asked otherwise
+    let symlinks = symlinks.unwrap_or(SymlinkPolicy::AsLink);
+    let app = window.app_handle().clone();
+    // Fail now if the connection is gone; the pool is taken when the copy starts
+    current_pool(&app, &connection_id)?;
+
+    // Generate a unique transfer ID and register it for cancel and pause
     let transfer_id = Uuid::new_v4().to_string();
-    let cancel_flag = Arc::new(AtomicBool::new(false));
+    let control = TransferControl::default();
     TRANSFER_CANCEL_MAP
         .lock()
         .unwrap()
-        .insert(transfer_id.clone(), cancel_flag.clone());
+        .insert(transfer_id.clone(), control.clone());
 
-    // Spawn a new task for the copy operation
+    // Queue the copy operation
     let transfer_id_return = transfer_id.clone();
-    tokio::spawn({
-        let transfer_id = transfer_id.clone();
-        let window = window.clone();
-        async move {
-            if is_directory {
-                copy_directory_recursive_with_progress(
-                    &sftp,
-                    &source_path,
-                    &dest_path,
-                    &window,
-                    &transfer_id,
-                    cancel_flag.clone(),
-                )?;
-            } else {
-                copy_file_with_progress(
-                    &sftp,
-                    &source_path,
-                    &dest_path,
-                    &window,
-                    &transfer_id,
-                    cancel_flag.clone(),
-                )?;
-            }
+    let transfer =
+        QueuedTransfer::new(&transfer_id, &connection_id, TransferKind::Copy, &dest_path);
+    app.state::<TransferQueue>()
+        .enqueue(&app, transfer, control.clone(), {
+            let app = app.clone();
+            let transfer_id = transfer_id.clone();
+            let window = window.clone();
+            async move {
+                let (pool, generation) = current_pool(&app, &connection_id)?;
+                let context = TransferContext {
+                    window: window.clone(),
+                    connection_id: connection_id.clone(),
+                    transfer_id: transfer_id.clone(),
+                    cancel_flag: control.cancel_flag,
+                    pause_flag: control.pause_flag,
+                    tree: None,
+                };
+                let result = run_blocking({
+                    let dest_path = dest_path.clone();
+                    move || {
+                        pool.with_channel(|sftp| {
+                            if is_directory {
+                                let symlinks = SymlinkWalk::new(symlinks);
+                                let root = on_ssh2(walk_root(sftp, &source_path, &symlinks))?;
+                                copy_step(
+                                    sftp,
+                                    pool.session(),
+                                    root,
+                                    &source_path,
+                                    &dest_path,
+                                    &symlinks,
+                                    &context,
+                                )
+                            } else {
+                                copy_file_with_progress(
+                                    sftp,
+                                    pool.session(),
+                                    &source_path,
+                                    &dest_path,
+                                    &context,
+                                )
+                            }
+                        })
+                    }
+                })
+                .await?;
+
+                if let Err(error) = result {
+                    return Err(reconnect_after(&app, &connection_id, generation, error).await);
+                }
 
-            // Remove the transfer ID from the cancel map
-            TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+                // Remove the transfer ID from the cancel map
+                TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
 
-            // Emit process_finished event
-            window
-                .emit(
-                    "process_finished",
-                    serde_json::json!({
-                        "connection_id": connection_id,
-                        "path": dest_path,
-                        "type": "copy",
-                        "transfer_id": transfer_id
-                    }),
-                )
-                .ok();
+                // Emit process_finished event
+                window
+                    .emit(
+                        "process_finished",
+                        serde_json::json!({
+                            "connection_id": connection_id,
+                            "path": dest_path,
+                            "type": "copy",
+                            "transfer_id": transfer_id
+                        }),
+                    )
+                    .ok();
 
-            Ok::<(), String>(())
-        }
-    });
+                Ok::<(), SftpError>(())
+            }
+        });
 
     Ok(transfer_id_return)
 }
 
 fn copy_file_with_progress(
     sftp: &ssh2::Sftp,
+    session: &ssh2::Session,
     src: &str,
     dst: &str,
-    window: &Window,
-    transfer_id: &str,
-    cancel_flag: Arc<AtomicBool>,
-) -> Result<(), String> {
+    context: &TransferContext,
+) -> Result<(), SftpError> {
     let mut src_file = sftp
         .open(Path::new(src))
-        .map_err(|e| format!("Failed to open source file: {}", e))?;
+        .map_err(|e| SftpError::ssh("Failed to open source file", e).with_path(src))?;
     let mut dst_file = sftp
         .create(Path::new(dst))
-        .map_err(|e| format!("Failed to create destination file: {}", e))?;
+        .map_err(|e| SftpError::ssh("Failed to create destination file", e).with_path(dst))?;
 
     let total_size = src_file
         .stat()
-        .map_err(|e| format!("Failed to stat source file: {}", e))?
+        .map_err(|e| SftpError::ssh("Failed to stat source file", e).with_path(src))?
         .size
         .unwrap_or(0);
 
@@ -463,17 +1234,18 @@ fn copy_file_with_progress(
     loop {
         let n = src_file
             .read(&mut buffer)
-            .map_err(|e| format!("Read error: {}", e))?;
+            .map_err(|e| SftpError::io("Read error", e, session).with_path(src))?;
         if n == 0 {
             break;
         }
         dst_file
             .write_all(&buffer[..n])
-            .map_err(|e| format!("Write error: {}", e))?;
+            .map_err(|e| SftpError::io("Write error", e, session).with_path(dst))?;
         transferred += n as u64;
 
         // Emit progress event
-        window
+        context
+            .window
             .emit(
                 "copy_progress",
                 serde_json::json!({
@@ -481,18 +1253,21 @@ fn copy_file_with_progress(
                     "transferred": transferred,
                     "total": total_size,
                     "type": "copy",
-                    "transfer_id": transfer_id
+                    "transfer_id": context.transfer_id,
+                    "is_hidden": is_hidden_path(dst)
                 }),
             )
             .ok();
 
-        // Check for cancellation
-        if cancel_flag.load(Ordering::Relaxed) {
-            window
+        // Check for cancellation, waiting first while the copy is paused
+        context.wait_while_paused_blocking();
+        if context.cancel_flag.load(Ordering::Relaxed) {
+            context
+                .window
                 .emit(
                     "transfer_cancelled",
                     serde_json::json!({
-                        "transfer_id": transfer_id,
+                        "transfer_id": context.transfer_id,
                         "type": "copy"
                     }),
                 )
@@ -503,7 +1278,7 @@ fn copy_file_with_progress(
     }
 
     if cancelled {
-        Err("Copy operation cancelled".to_string())
+        Err(SftpError::cancelled("Copy operation cancelled"))
     } else {
         Ok(())
     }
@@ -511,20 +1286,26 @@ fn copy_file_with_progress(
 
 fn copy_directory_recursive_with_progress(
     sftp: &ssh2::Sftp,
-    src: &str,
+    session: &ssh2::Session,
+    src_dir: &WalkDir,
     dst: &str,
-    window: &Window,
-    transfer_id: &str,
-    cancel_flag: Arc<AtomicBool>,
-) -> Result<(), String> {
+    symlinks: &SymlinkWalk,
+    context: &TransferContext,
+) -> Result<(), SftpError> {
+    let src = src_dir.path.as_str();
     let src_path = Path::new(src);
     let dst_path = Path::new(dst);
-    sftp.mkdir(dst_path, 0o755)
-        .map_err(|e| format!("Failed to create destination directory {}: {}", dst, e))?;
-
-    let entries = sftp
-        .readdir(src_path)
-        .map_err(|e| format!("Failed to read source directory {}: {}", src, e))?;
+    sftp.mkdir(dst_path, 0o755).map_err(|e| {
+        SftpError::ssh(
+            &format!("Failed to create destination directory {}", dst),
+            e,
+        )
+        .with_path(dst)
+    })?;
+
+    let entries = sftp.readdir(src_path).map_err(|e| {
+        SftpError::ssh(&format!("Failed to read source directory {}", src), e).with_path(src)
+    })?;
 
     for (file_path, stat) in entries {
         let name = file_path.file_name().unwrap_or_default().to_string_lossy();
@@ -534,52 +1315,57 @@ fn copy_directory_recursive_with_progress(
         let src_child = file_path.to_string_lossy().replace("\\", "/");
         let dst_child = format!("{}/{}", dst, name);
 
-        if stat.is_dir() {
-            copy_directory_recursive_with_progress(
-                sftp,
-                &src_child,
-                &dst_child,
-                window,
-                transfer_id,
-                cancel_flag.clone(),
-            )?;
-        } else {
-            copy_file_with_progress(
-                sftp,
-                &src_child,
-                &dst_child,
-                window,
-                transfer_id,
-                cancel_flag.clone(),
-            )?;
-        }
+        let step = on_ssh2(walk_step(sftp, src_dir, &src_child, &stat, symlinks))?;
+        copy_step(
+            sftp, session, step, &src_child, &dst_child, symlinks, context,
+        )?;
 
-        // Check for cancellation
-        if cancel_flag.load(Ordering::Relaxed) {
-            return Err("Copy operation cancelled".to_string());
+        // Check for cancellation, waiting first while the copy is paused
+        context.wait_while_paused_blocking();
+        if context.cancel_flag.load(Ordering::Relaxed) {
+            return Err(SftpError::cancelled("Copy operation cancelled"));
         }
     }
 
     Ok(())
 }
 
+// Copy one entry of a directory copy the way its walk step says
+fn copy_step(
+    sftp: &ssh2::Sftp,
+    session: &ssh2::Session,
+    step: WalkStep,
+    src: &str,
+    dst: &str,
+    symlinks: &SymlinkWalk,
+    context: &TransferContext,
+) -> Result<(), SftpError> {
+    match step {
+        WalkStep::Directory(src_dir) => {
+            copy_directory_recursive_with_progress(sftp, session, &src_dir, dst, symlinks, context)
+        }
+        WalkStep::File(_) => copy_file_with_progress(sftp, session, src, dst, context),
+        WalkStep::Link(_) => {
+            let target = sftp
+                .readlink(Path::new(src))
+                .map_err(|e| SftpError::ssh("Failed to read link", e).with_path(src))?;
+            sftp.symlink(&target, Path::new(dst))
+                .map_err(|e| SftpError::ssh("Failed to create link", e).with_path(dst))
+        }
+        WalkStep::Skip => Ok(()),
+    }
+}
+
 #[tauri::command]
 pub async fn move_item(
     connection_id: String,
     source_path: String,
     dest_path: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<(), String> {
-    let mut conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get_mut(&connection_id)
-        .ok_or("Connection not found")?;
-
-    let sftp = session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-    sftp.rename(Path::new(&source_path), Path::new(&dest_path), None)
-        .map_err(|e| format!("Failed to move item: {}", e))?;
-    Ok(())
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    with_reconnect(&app, &connection_id, false, move |sftp| {
+        sftp.rename(Path::new(&source_path), Path::new(&dest_path), None)
+            .map_err(|e| SftpError::ssh("Failed to move item", e).with_path(&source_path))
+    })
+    .await
 }
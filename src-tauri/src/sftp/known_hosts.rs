@@ -0,0 +1,440 @@
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::types::*;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use ssh2::{
+    CheckResult, HashType, HostKeyType, KnownHostFileKind, KnownHostKeyFormat, KnownHosts, Session,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, Window};
+
+// Host keys seen during a handshake that are not trusted yet, keyed by "host:port".
+// They are kept until the user accepts (or ignores) the trust-on-first-use prompt.
+static PENDING_HOST_KEYS: Lazy<Mutex<HashMap<String, PendingHostKey>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct PendingHostKey {
+    key: Vec<u8>,
+    key_type: HostKeyType,
+    fingerprint: String,
+}
+
+// Verify the host key of a freshly handshaked session against the user's
+// ~/.ssh/known_hosts and the app-managed known_hosts file
+pub fn verify_host_key(
+    session: &Session,
+    host: &str,
+    port: u16,
+    app: &AppHandle,
+) -> Result<(), SftpError> {
+    let (key, key_type) = session.host_key().ok_or_else(|| {
+        SftpError::new(
+            ErrorKind::ConnectionFailed,
+            "Server did not provide a host key",
+        )
+    })?;
+    let fingerprint = session
+        .host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)))
+        .ok_or_else(|| SftpError::other("Failed to compute host key fingerprint"))?;
+
+    let mut known_hosts = session
+        .known_hosts()
+        .map_err(|e| SftpError::ssh("Failed to initialize known hosts", e))?;
+    if let Some(path) = user_known_hosts_path(app) {
+        read_user_known_hosts_file(&mut known_hosts, &path);
+    }
+    if let Some(path) = app_known_hosts_path(app) {
+        read_known_hosts_file(&mut known_hosts, &path)?;
+    }
+    retain_key_type(&known_hosts, key_type)?;
+
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => {
+            PENDING_HOST_KEYS.lock().unwrap().insert(
+                format!("{}:{}", host, port),
+                PendingHostKey {
+                    key: key.to_vec(),
+                    key_type,
+                    fingerprint: fingerprint.clone(),
+                },
+            );
+
+            app.emit(
+                "host_key_unknown",
+                serde_json::json!({
+                    "host": host,
+                    "port": port,
+                    "key_type": key_type_name(key_type),
+                    "fingerprint": fingerprint
+                }),
+            )
+            .ok();
+
+            Err(SftpError::new(
+                ErrorKind::HostKeyUnknown,
+                format!(
+                    "Host key for {}:{} is not trusted yet ({} {})",
+                    host,
+                    port,
+                    key_type_name(key_type),
+                    fingerprint
+                ),
+            ))
+        }
+        CheckResult::Mismatch => {
+            app.emit(
+                "host_key_changed",
+                serde_json::json!({
+                    "host": host,
+                    "port": port,
+                    "key_type": key_type_name(key_type),
+                    "fingerprint": fingerprint
+                }),
+            )
+            .ok();
+
+            Err(SftpError::new(
+                ErrorKind::HostKeyChanged,
+                format!(
+                    "HOST KEY CHANGED for {}:{}: the server presented {} {}, which does not match the trusted key. \
+                     Refusing to connect; remove the old key if the change is expected",
+                    host,
+                    port,
+                    key_type_name(key_type),
+                    fingerprint
+                ),
+            ))
+        }
+        CheckResult::Failure => Err(SftpError::other(format!(
+            "Failed to check host key for {}:{}",
+            host, port
+        ))),
+    }
+}
+
+// List trusted host keys from both the user's and the app-managed known_hosts files
+#[tauri::command]
+pub async fn list_known_hosts(window: Window) -> Result<Vec<KnownHostEntry>, SftpError> {
+    let session = Session::new().map_err(|e| SftpError::ssh("Failed to create session", e))?;
+    let mut entries = Vec::new();
+
+    let sources = [
+        ("user", user_known_hosts_path(window.app_handle())),
+        ("app", app_known_hosts_path(window.app_handle())),
+    ];
+
+    for (source, path) in sources.iter() {
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let mut known_hosts = session
+            .known_hosts()
+            .map_err(|e| SftpError::ssh("Failed to initialize known hosts", e))?;
+        if *source == "user" {
+            read_user_known_hosts_file(&mut known_hosts, path);
+        } else {
+            read_known_hosts_file(&mut known_hosts, path)?;
+        }
+
+        let hosts = known_hosts
+            .hosts()
+            .map_err(|e| SftpError::ssh("Failed to read known hosts", e))?;
+
+        for host in hosts {
+            let line = known_hosts
+                .write_string(&host, KnownHostFileKind::OpenSSH)
+                .unwrap_or_default();
+            let key_type = line.split_whitespace().nth(1).unwrap_or("unknown");
+
+            entries.push(KnownHostEntry {
+                host: host.name().unwrap_or("(hashed)").to_string(),
+                key_type: key_type.to_string(),
+                fingerprint: fingerprint_from_base64(host.key()),
+                source: source.to_string(),
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+// Trust the host key that was offered for host:port during the last connection attempt
+#[tauri::command]
+pub async fn accept_host_key(
+    host: String,
+    port: u16,
+    fingerprint: String,
+    window: Window,
+) -> Result<(), SftpError> {
+    let pending_key = format!("{}:{}", host, port);
+    let pending = PENDING_HOST_KEYS
+        .lock()
+        .unwrap()
+        .remove(&pending_key)
+        .ok_or_else(|| {
+            SftpError::new(
+                ErrorKind::NotFound,
+                format!("No pending host key for {}:{}", host, port),
+            )
+        })?;
+
+    if pending.fingerprint != fingerprint {
+        return Err(SftpError::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Fingerprint mismatch for {}:{}: expected {}, got {}",
+                host, port, pending.fingerprint, fingerprint
+            ),
+        ));
+    }
+
+    let path =
+        app_known_hosts_path(window.app_handle()).ok_or("App data directory is not available")?;
+    let session = Session::new().map_err(|e| SftpError::ssh("Failed to create session", e))?;
+    let mut known_hosts = session
+        .known_hosts()
+        .map_err(|e| SftpError::ssh("Failed to initialize known hosts", e))?;
+    read_known_hosts_file(&mut known_hosts, &path)?;
+
+    known_hosts
+        .add(
+            &known_hosts_name(&host, port),
+            &pending.key,
+            "added by Fileman",
+            KnownHostKeyFormat::from(pending.key_type),
+        )
+        .map_err(|e| {
+            SftpError::ssh("Failed to add host key", e).with_path(path.to_string_lossy())
+        })?;
+
+    write_known_hosts_file(&known_hosts, &path)
+}
+
+// Remove trusted keys for host:port from the app-managed known_hosts file.
+// The user's own ~/.ssh/known_hosts is never modified.
+#[tauri::command]
+pub async fn remove_known_host(host: String, port: u16, window: Window) -> Result<(), SftpError> {
+    let path =
+        app_known_hosts_path(window.app_handle()).ok_or("App data directory is not available")?;
+    let session = Session::new().map_err(|e| SftpError::ssh("Failed to create session", e))?;
+    let mut known_hosts = session
+        .known_hosts()
+        .map_err(|e| SftpError::ssh("Failed to initialize known hosts", e))?;
+    read_known_hosts_file(&mut known_hosts, &path)?;
+
+    let name = known_hosts_name(&host, port);
+    let hosts = known_hosts
+        .hosts()
+        .map_err(|e| SftpError::ssh("Failed to read known hosts", e))?;
+
+    let mut removed = 0;
+    for entry in hosts
+        .iter()
+        .filter(|entry| entry.name() == Some(name.as_str()))
+    {
+        known_hosts.remove(entry).map_err(|e| {
+            SftpError::ssh("Failed to remove host key", e).with_path(path.to_string_lossy())
+        })?;
+        removed += 1;
+    }
+
+    if removed == 0 {
+        return Err(SftpError::new(
+            ErrorKind::NotFound,
+            format!("No trusted key found for {}", name),
+        ));
+    }
+
+    write_known_hosts_file(&known_hosts, &path)
+}
+
+fn user_known_hosts_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .home_dir()
+        .ok()
+        .map(|home| home.join(".ssh").join("known_hosts"))
+}
+
+fn app_known_hosts_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join("known_hosts"))
+}
+
+fn read_known_hosts_file(known_hosts: &mut KnownHosts, path: &Path) -> Result<(), SftpError> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    known_hosts
+        .read_file(path, KnownHostFileKind::OpenSSH)
+        .map_err(|e| {
+            SftpError::ssh("Failed to read known hosts", e).with_path(path.to_string_lossy())
+        })?;
+
+    Ok(())
+}
+
+// The user's own known_hosts may hold lines libssh2 can't parse, e.g.
+// @cert-authority markers or keys of newer types. Those lines are skipped
+// silently, as is a file that can't be read, so one of them doesn't stop every
+// connection; the rest of the file still counts.
+fn read_user_known_hosts_file(known_hosts: &mut KnownHosts, path: &Path) {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(_) => return,
+    };
+
+    for line in String::from_utf8_lossy(&contents).lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        known_hosts.read_str(line, KnownHostFileKind::OpenSSH).ok();
+    }
+}
+
+// libssh2 compares the offered key against trusted keys of every type, so a
+// server offering ssh-ed25519 while only its ssh-rsa key is trusted would look
+// changed. Keys of other types are dropped before the check; an unknown type
+// keeps them all.
+fn retain_key_type(known_hosts: &KnownHosts, key_type: HostKeyType) -> Result<(), SftpError> {
+    if matches!(key_type, HostKeyType::Unknown) {
+        return Ok(());
+    }
+
+    let hosts = known_hosts
+        .hosts()
+        .map_err(|e| SftpError::ssh("Failed to read known hosts", e))?;
+    for host in hosts
+        .iter()
+        .filter(|host| key_blob_type(host.key()).as_deref() != Some(key_type_name(key_type)))
+    {
+        known_hosts
+            .remove(host)
+            .map_err(|e| SftpError::ssh("Failed to filter known hosts", e))?;
+    }
+
+    Ok(())
+}
+
+// The key type named at the start of a base64 public key blob
+fn key_blob_type(key: &str) -> Option<String> {
+    let blob = STANDARD.decode(key).ok()?;
+    let len = blob
+        .get(..4)?
+        .iter()
+        .fold(0usize, |len, byte| len << 8 | *byte as usize);
+    String::from_utf8(blob.get(4..4 + len)?.to_vec()).ok()
+}
+
+fn write_known_hosts_file(known_hosts: &KnownHosts, path: &Path) -> Result<(), SftpError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            SftpError::local("Failed to create directory", e).with_path(parent.to_string_lossy())
+        })?;
+    }
+
+    known_hosts
+        .write_file(path, KnownHostFileKind::OpenSSH)
+        .map_err(|e| {
+            SftpError::ssh("Failed to write known hosts", e).with_path(path.to_string_lossy())
+        })
+}
+
+// OpenSSH writes non-default ports as "[host]:port"
+fn known_hosts_name(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn fingerprint_from_base64(key: &str) -> String {
+    match STANDARD.decode(key) {
+        Ok(blob) => format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(&blob))),
+        Err(_) => String::new(),
+    }
+}
+
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A public key blob: the type name, then opaque key data
+    fn key_blob(key_type: &str, data: &[u8]) -> Vec<u8> {
+        let mut blob = Vec::new();
+        for part in [key_type.as_bytes(), data] {
+            blob.extend_from_slice(&(part.len() as u32).to_be_bytes());
+            blob.extend_from_slice(part);
+        }
+        blob
+    }
+
+    fn trusted(lines: &[(&str, &[u8])]) -> KnownHosts {
+        let session = Session::new().unwrap();
+        let mut known_hosts = session.known_hosts().unwrap();
+        for (key_type, data) in lines {
+            let line = format!(
+                "example.com {} {}",
+                key_type,
+                STANDARD.encode(key_blob(key_type, data))
+            );
+            known_hosts
+                .read_str(&line, KnownHostFileKind::OpenSSH)
+                .unwrap();
+        }
+        known_hosts
+    }
+
+    #[test]
+    fn key_of_another_type_is_not_a_mismatch() {
+        let known_hosts = trusted(&[("ssh-rsa", &[1; 64])]);
+        retain_key_type(&known_hosts, HostKeyType::Ed25519).unwrap();
+
+        let offered = key_blob("ssh-ed25519", &[2; 32]);
+        assert!(matches!(
+            known_hosts.check_port("example.com", 22, &offered),
+            CheckResult::NotFound
+        ));
+    }
+
+    #[test]
+    fn key_of_the_same_type_is_still_compared() {
+        let known_hosts = trusted(&[("ssh-rsa", &[1; 64]), ("ssh-ed25519", &[3; 32])]);
+        retain_key_type(&known_hosts, HostKeyType::Ed25519).unwrap();
+
+        let offered = key_blob("ssh-ed25519", &[2; 32]);
+        assert!(matches!(
+            known_hosts.check_port("example.com", 22, &offered),
+            CheckResult::Mismatch
+        ));
+        let trusted_key = key_blob("ssh-ed25519", &[3; 32]);
+        assert!(matches!(
+            known_hosts.check_port("example.com", 22, &trusted_key),
+            CheckResult::Match
+        ));
+    }
+}
@@ -1,17 +1,41 @@
+pub mod async_engine;
+pub mod auth;
 pub mod connection;
 pub mod directory_operations;
+pub mod engine;
+pub mod error;
 pub mod file_operations;
+pub mod known_hosts;
+pub mod owners;
+pub mod permissions;
+pub mod pool;
+pub mod proxy;
+pub mod queue;
+pub mod reconnect;
+pub mod ssh_config;
 pub mod storage_info;
+pub mod transfers;
+pub mod tunnel;
 pub mod utils;
+pub mod walk;
 
 // Re-export commonly used functions
-pub use connection::{connect_sftp, disconnect_sftp};
+pub use auth::respond_keyboard_interactive;
+pub use connection::{connect_sftp, disconnect_sftp, spawn_keepalive_task};
 pub use directory_operations::{
     cancel_directory_size, create_directory, delete_directory, delete_directory_recursive,
-    fetch_directory_size, list_directory,
+    fetch_directory_size, list_directory, stat_item,
 };
 pub use file_operations::{
-    cancel_transfer, copy_item, delete_item, download_file, get_active_transfers, move_item,
-    rename_item, upload_file,
+    cancel_transfer, copy_item, delete_item, download_directory, download_file,
+    get_active_transfers, move_item, pause_transfer, rename_item, upload_directory, upload_file,
 };
+pub use known_hosts::{accept_host_key, list_known_hosts, remove_known_host};
+pub use permissions::{set_owner, set_permissions};
+pub use queue::{
+    clear_finished_transfers, get_transfer_queue, move_transfer, prioritize_transfer,
+    remove_transfer, set_transfer_limits,
+};
+pub use ssh_config::{list_ssh_config_hosts, resolve_ssh_config_host};
 pub use storage_info::fetch_storage_info;
+pub use transfers::{discard_transfer, get_resumable_transfers, resume_transfer};
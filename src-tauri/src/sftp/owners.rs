@@ -0,0 +1,138 @@
+use crate::sftp::utils::{run_blocking, run_remote_command, shell_quote};
+use crate::types::*;
+use ssh2::Session;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+// User and group names of one connection's server by uid and gid. Neither SFTP
+// client hands out the longname of directory entries, so names come from
+// getent over exec. Ids the server has no name for are cached as None so they
+// aren't asked for again.
+#[derive(Default)]
+pub struct OwnerNames {
+    users: Mutex<HashMap<u32, Option<String>>>,
+    groups: Mutex<HashMap<u32, Option<String>>>,
+}
+
+impl OwnerNames {
+    pub fn user(&self, uid: u32) -> Option<String> {
+        self.users.lock().unwrap().get(&uid).cloned().flatten()
+    }
+
+    pub fn group(&self, gid: u32) -> Option<String> {
+        self.groups.lock().unwrap().get(&gid).cloned().flatten()
+    }
+
+    // uid of a user name, asking the server if it isn't cached
+    pub fn uid(&self, session: &Session, name: &str) -> Option<u32> {
+        lookup_name(&self.users, session, "passwd", name)
+    }
+
+    pub fn gid(&self, session: &Session, name: &str) -> Option<u32> {
+        lookup_name(&self.groups, session, "group", name)
+    }
+
+    // Look up the ids that aren't cached yet, one getent call per database
+    pub fn resolve(&self, session: &Session, uids: &[u32], gids: &[u32]) {
+        lookup(&self.users, session, "passwd", uids);
+        lookup(&self.groups, session, "group", gids);
+    }
+}
+
+// Fill in the owner and group names of listed files. Names are a convenience,
+// so a failed lookup leaves them empty instead of failing the listing.
+pub async fn resolve_owners(app: &AppHandle, connection_id: &str, files: &mut [FileItem]) {
+    let manager = app.state::<ConnectionManager>();
+    let (pool, names) = match (
+        manager.sftp_pool(connection_id),
+        manager.owner_names(connection_id),
+    ) {
+        (Some((pool, _)), Some(names)) => (pool, names),
+        _ => return,
+    };
+
+    let uids: Vec<u32> = files.iter().filter_map(|file| file.uid).collect();
+    let gids: Vec<u32> = files.iter().filter_map(|file| file.gid).collect();
+    let lookup_names = names.clone();
+    let resolved = run_blocking(move || lookup_names.resolve(pool.session(), &uids, &gids)).await;
+    if resolved.is_err() {
+        return;
+    }
+
+    for file in files.iter_mut() {
+        file.owner = file.uid.and_then(|uid| names.user(uid));
+        file.group = file.gid.and_then(|gid| names.group(gid));
+    }
+}
+
+fn lookup(
+    cache: &Mutex<HashMap<u32, Option<String>>>,
+    session: &Session,
+    database: &str,
+    ids: &[u32],
+) {
+    let missing: BTreeSet<u32> = {
+        let cache = cache.lock().unwrap();
+        ids.iter()
+            .copied()
+            .filter(|id| !cache.contains_key(id))
+            .collect()
+    };
+    if missing.is_empty() {
+        return;
+    }
+
+    // getent exits non-zero when any id is unknown, and isn't installed everywhere;
+    // either way the ids missing from its output have no name
+    let ids: Vec<String> = missing.iter().map(|id| id.to_string()).collect();
+    let command = format!("getent {} {} || true", database, ids.join(" "));
+    let output = match run_remote_command(session, &command) {
+        Ok(output) => output,
+        Err(_) => return,
+    };
+
+    let mut names = parse_getent_output(&output);
+    let mut cache = cache.lock().unwrap();
+    for id in missing {
+        cache.insert(id, names.remove(&id));
+    }
+}
+
+fn lookup_name(
+    cache: &Mutex<HashMap<u32, Option<String>>>,
+    session: &Session,
+    database: &str,
+    name: &str,
+) -> Option<u32> {
+    let cached = cache
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(_, cached)| cached.as_deref() == Some(name))
+        .map(|(id, _)| *id);
+    if cached.is_some() {
+        return cached;
+    }
+
+    let command = format!("getent {} {} || true", database, shell_quote(name));
+    let output = run_remote_command(session, &command).ok()?;
+    let (id, name) = parse_getent_output(&output)
+        .into_iter()
+        .find(|(_, found)| found == name)?;
+    cache.lock().unwrap().insert(id, Some(name));
+    Some(id)
+}
+
+// Names by id from getent passwd or group output (name:password:id:...)
+fn parse_getent_output(output: &str) -> HashMap<u32, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,357 @@
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::sftp::file_operations::{TransferContext, TransferControl, TRANSFER_CANCEL_MAP};
+use crate::sftp::queue::TransferQueue;
+use crate::sftp::reconnect::{current_pool, reconnect_after};
+use crate::sftp::utils::{run_blocking, ModeSpec};
+use crate::types::{ConnectionManager, QueuedTransfer, TransferKind};
+use ssh2::{FileStat, Sftp};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use tauri::{Emitter, Manager, Window};
+use uuid::Uuid;
+
+// Change the mode of a path, and with `recursive` of everything below it.
+// `mode` applies to every entry; `file_mode` and `directory_mode` override it
+// for their kind (e.g. 644 and 755), and an entry without a mode is left alone.
+// Progress goes out as chmod_progress; failures of single paths are collected
+// and reported by process_finished instead of aborting the walk.
+#[tauri::command]
+pub async fn set_permissions(
+    connection_id: String,
+    path: String,
+    mode: Option<String>,
+    file_mode: Option<String>,
+    directory_mode: Option<String>,
+    recursive: bool,
+    window: Window,
+) -> Result<String, SftpError> {
+    let parse = |spec: Option<String>| {
+        spec.map(|spec| ModeSpec::parse(&spec))
+            .transpose()
+            .map_err(|e| SftpError::new(ErrorKind::InvalidInput, e))
+    };
+    let modes = ModeChange {
+        files: parse(file_mode.or_else(|| mode.clone()))?,
+        directories: parse(directory_mode.or(mode))?,
+    };
+    if modes.files.is_none() && modes.directories.is_none() {
+        return Err(SftpError::new(ErrorKind::InvalidInput, "No mode given"));
+    }
+
+    spawn_setstat_walk(
+        connection_id,
+        path,
+        recursive,
+        TransferKind::Chmod,
+        window,
+        move |stat| {
+            let spec = if stat.is_dir() {
+                &modes.directories
+            } else {
+                &modes.files
+            };
+            let spec = match spec {
+                Some(spec) => spec,
+                None => return Ok(None),
+            };
+            let mode = stat.perm.unwrap_or(0);
+            let perm = spec.apply(mode);
+            Ok((perm != mode & 0o7777).then_some(FileStat {
+                perm: Some(perm),
+                ..no_change()
+            }))
+        },
+    )
+}
+
+// Change the owner and/or group of a path, and with `recursive` of everything
+// below it. Each is a numeric id or a name looked up on the server. Progress
+// and failures are reported like set_permissions does, as chown_progress and
+// process_finished.
+#[tauri::command]
+pub async fn set_owner(
+    connection_id: String,
+    path: String,
+    owner: Option<String>,
+    group: Option<String>,
+    recursive: bool,
+    window: Window,
+) -> Result<String, SftpError> {
+    if owner.is_none() && group.is_none() {
+        return Err(SftpError::new(
+            ErrorKind::InvalidInput,
+            "No owner or group given",
+        ));
+    }
+
+    let app = window.app_handle().clone();
+    let names = app
+        .state::<ConnectionManager>()
+        .owner_names(&connection_id)
+        .ok_or_else(SftpError::connection_not_found)?;
+    let (pool, _) = current_pool(&app, &connection_id)?;
+    let (uid, gid) = run_blocking(move || {
+        let uid = owner
+            .map(|owner| resolve_id(&owner, "user", |name| names.uid(pool.session(), name)))
+            .transpose()?;
+        let gid = group
+            .map(|group| resolve_id(&group, "group", |name| names.gid(pool.session(), name)))
+            .transpose()?;
+        Ok::<_, SftpError>((uid, gid))
+    })
+    .await?
+    .map_err(|e| e.with_path(&path))?;
+
+    spawn_setstat_walk(
+        connection_id,
+        path,
+        recursive,
+        TransferKind::Chown,
+        window,
+        move |stat| {
+            let changed = uid.is_some_and(|uid| stat.uid != Some(uid))
+                || gid.is_some_and(|gid| stat.gid != Some(gid));
+            if !changed {
+                return Ok(None);
+            }
+
+            // SFTP sets uid and gid together, so the one not asked for is kept as
+            // it is; without it the server would be sent 0, i.e. root
+            match (uid.or(stat.uid), gid.or(stat.gid)) {
+                (Some(uid), Some(gid)) => Ok(Some(FileStat {
+                    uid: Some(uid),
+                    gid: Some(gid),
+                    ..no_change()
+                })),
+                _ => Err(SftpError::new(
+                    ErrorKind::Unsupported,
+                    "The server did not report the current owner and group",
+                )),
+            }
+        },
+    )
+}
+
+// A numeric id as given, or the id of a user or group name
+fn resolve_id<F>(spec: &str, kind: &str, lookup: F) -> Result<u32, SftpError>
+where
+    F: FnOnce(&str) -> Option<u32>,
+{
+    let spec = spec.trim();
+    if let Ok(id) = spec.parse() {
+        return Ok(id);
+    }
+    lookup(spec).ok_or_else(|| {
+        SftpError::new(
+            ErrorKind::InvalidInput,
+            format!("No {} named {} on the server", kind, spec),
+        )
+    })
+}
+
+// Queue a walk of a path on the blocking pool, applying the setstat `change`
+// returns for each entry (None when it needs none). It waits and runs in the
+// transfer queue like copy_item, so it can be cancelled and paused and an error
+// that ends it shows up as a failed transfer. Emits {kind}_progress per entry
+// and process_finished with the failed paths.
+fn spawn_setstat_walk<F>(
+    connection_id: String,
+    path: String,
+    recursive: bool,
+    transfer_kind: TransferKind,
+    window: Window,
+    change: F,
+) -> Result<String, SftpError>
+where
+    F: Fn(&FileStat) -> Result<Option<FileStat>, SftpError> + Send + 'static,
+{
+    let kind = match transfer_kind {
+        TransferKind::Chmod => "chmod",
+        _ => "chown",
+    };
+    let app = window.app_handle().clone();
+    // Fail now if the connection is gone; the pool is taken when the walk starts
+    current_pool(&app, &connection_id)?;
+
+    // Generate a unique transfer ID and register it for cancel and pause
+    let transfer_id = Uuid::new_v4().to_string();
+    let control = TransferControl::default();
+    TRANSFER_CANCEL_MAP
+        .lock()
+        .unwrap()
+        .insert(transfer_id.clone(), control.clone());
+
+    let transfer_id_return = transfer_id.clone();
+    let transfer = QueuedTransfer::new(&transfer_id, &connection_id, transfer_kind, &path);
+    app.state::<TransferQueue>()
+        .enqueue(&app, transfer, control.clone(), {
+            let app = app.clone();
+            async move {
+                let (pool, generation) = current_pool(&app, &connection_id)?;
+                let context = TransferContext {
+                    window: window.clone(),
+                    connection_id: connection_id.clone(),
+                    transfer_id: transfer_id.clone(),
+                    cancel_flag: control.cancel_flag,
+                    pause_flag: control.pause_flag,
+                    tree: None,
+                };
+                let result = run_blocking({
+                    let path = path.clone();
+                    move || {
+                        pool.with_channel(|sftp| {
+                            let mut walk = SetstatWalk {
+                                sftp,
+                                change: &change,
+                                kind,
+                                recursive,
+                                context: &context,
+                                processed: 0,
+                                failures: Vec::new(),
+                            };
+                            walk.root(&path)?;
+                            Ok((walk.processed, walk.failures))
+                        })
+                    }
+                })
+                .await?;
+
+                let (processed, failures) = match result {
+                    Ok(summary) => summary,
+                    Err(error) => {
+                        return Err(reconnect_after(&app, &connection_id, generation, error).await)
+                    }
+                };
+
+                TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+
+                window
+                    .emit(
+                        "process_finished",
+                        serde_json::json!({
+                            "connection_id": connection_id,
+                            "path": path,
+                            "type": kind,
+                            "transfer_id": transfer_id,
+                            "processed": processed,
+                            "failures": failures
+                        }),
+                    )
+                    .ok();
+
+                Ok::<(), SftpError>(())
+            }
+        });
+
+    Ok(transfer_id_return)
+}
+
+// setstat attributes that leave everything unchanged
+fn no_change() -> FileStat {
+    FileStat {
+        size: None,
+        uid: None,
+        gid: None,
+        perm: None,
+        atime: None,
+        mtime: None,
+    }
+}
+
+// Mode for each kind of entry; None leaves that kind unchanged
+struct ModeChange {
+    files: Option<ModeSpec>,
+    directories: Option<ModeSpec>,
+}
+
+struct SetstatWalk<'a> {
+    sftp: &'a Sftp,
+    change: &'a dyn Fn(&FileStat) -> Result<Option<FileStat>, SftpError>,
+    kind: &'static str, // "chmod" or "chown"
+    recursive: bool,
+    context: &'a TransferContext,
+    processed: u64,
+    failures: Vec<SftpError>,
+}
+
+impl SetstatWalk<'_> {
+    // The path itself is followed if it's a symlink, like chmod and chown do for their arguments
+    fn root(&mut self, path: &str) -> Result<(), SftpError> {
+        match self.sftp.stat(Path::new(path)) {
+            Ok(stat) => self.visit(path, &stat),
+            Err(e) => self.fail(SftpError::ssh("Failed to stat", e).with_path(path)),
+        }
+    }
+
+    // Change one entry and walk into it if it's a directory. Only cancellation
+    // and a lost connection end the walk; anything else is recorded as a failure.
+    fn visit(&mut self, path: &str, stat: &FileStat) -> Result<(), SftpError> {
+        self.context.wait_while_paused_blocking();
+        if self.context.cancel_flag.load(Ordering::Relaxed) {
+            self.context
+                .window
+                .emit(
+                    "transfer_cancelled",
+                    serde_json::json!({
+                        "transfer_id": self.context.transfer_id,
+                        "type": self.kind
+                    }),
+                )
+                .ok();
+            return Err(SftpError::cancelled("Attribute change cancelled"));
+        }
+
+        match (self.change)(stat) {
+            Ok(Some(change)) => {
+                if let Err(e) = self.sftp.setstat(Path::new(path), change) {
+                    let context = format!("{} failed", self.kind);
+                    self.fail(SftpError::ssh(&context, e).with_path(path))?;
+                }
+            }
+            Ok(None) => {}
+            Err(error) => self.fail(error.with_path(path))?,
+        }
+
+        self.processed += 1;
+        self.context
+            .window
+            .emit(
+                &format!("{}_progress", self.kind),
+                serde_json::json!({
+                    "transfer_id": self.context.transfer_id,
+                    "path": path,
+                    "processed": self.processed,
+                    "failed": self.failures.len()
+                }),
+            )
+            .ok();
+
+        if !stat.is_dir() || !self.recursive {
+            return Ok(());
+        }
+
+        let entries = match self.sftp.readdir(Path::new(path)) {
+            Ok(entries) => entries,
+            Err(e) => {
+                return self.fail(SftpError::ssh("Failed to read directory", e).with_path(path))
+            }
+        };
+        for (child, stat) in entries {
+            // Links are left alone: setstat on one changes its target, which may be
+            // anywhere on the server
+            if stat.file_type().is_symlink() {
+                continue;
+            }
+            self.visit(&child.to_string_lossy().replace("\\", "/"), &stat)?;
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, error: SftpError) -> Result<(), SftpError> {
+        if error.transport_lost {
+            return Err(error);
+        }
+        self.failures.push(error);
+        Ok(())
+    }
+}
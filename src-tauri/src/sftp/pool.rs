@@ -0,0 +1,68 @@
+use crate::sftp::error::SftpError;
+use ssh2::{Session, Sftp};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// Idle channels kept per session; more are opened under load and closed when returned
+const MAX_IDLE_CHANNELS: usize = 4;
+
+// SFTP channels of one session, kept open between commands so each command
+// doesn't pay for opening a new subsystem channel. A reconnect gets a new pool.
+pub struct SftpPool {
+    session: Session,
+    idle: Mutex<Vec<Sftp>>,
+    closed: AtomicBool,
+}
+
+impl SftpPool {
+    pub fn new(session: Session) -> Self {
+        SftpPool {
+            session,
+            idle: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    // Run blocking work on a checked-out channel and return the channel afterwards.
+    // A channel that failed is closed instead; the next checkout opens a new one.
+    pub fn with_channel<T, F>(&self, work: F) -> Result<T, SftpError>
+    where
+        F: FnOnce(&Sftp) -> Result<T, SftpError>,
+    {
+        let idle = self.idle.lock().unwrap().pop();
+        let sftp = match idle {
+            Some(sftp) => sftp,
+            None => self
+                .session
+                .sftp()
+                .map_err(|e| SftpError::ssh("Failed to create SFTP channel", e))?,
+        };
+
+        let result = work(&sftp);
+        if !matches!(&result, Err(error) if error.channel_failed) {
+            self.check_in(sftp);
+        }
+        result
+    }
+
+    // Close every idle channel. Channels still checked out are closed when their
+    // work finishes. Closing talks to the server, so it runs on the blocking pool.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let idle = std::mem::take(&mut *self.idle.lock().unwrap());
+        if !idle.is_empty() {
+            tokio::task::spawn_blocking(move || drop(idle));
+        }
+    }
+
+    fn check_in(&self, sftp: Sftp) {
+        let mut idle = self.idle.lock().unwrap();
+        if !self.closed.load(Ordering::SeqCst) && idle.len() < MAX_IDLE_CHANNELS {
+            idle.push(sftp);
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use crate::sftp::utils::connect_with_timeout;
+use crate::types::*;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::time::Duration;
+
+// Upper bound for the HTTP proxy response headers we are willing to read
+const MAX_HTTP_RESPONSE_HEADERS: usize = 16 * 1024;
+
+// Open a TCP stream to host:port through the given proxy. The returned stream
+// is positioned right after the proxy negotiation and ready for the SSH handshake.
+// The timeout applies to connecting to the proxy and to each step of the negotiation.
+// Errors keep the kind of the underlying failure, so a timeout is told apart.
+pub fn connect_via_proxy(
+    proxy: &ProxyConfig,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> io::Result<TcpStream> {
+    let connect_error = |e: io::Error| {
+        with_context(
+            &format!("Failed to connect to proxy {}:{}", proxy.host, proxy.port),
+            e,
+        )
+    };
+
+    let mut stream =
+        connect_with_timeout(&proxy.host, proxy.port, timeout).map_err(connect_error)?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(connect_error)?;
+
+    match proxy.kind {
+        ProxyKind::Socks5 => socks5_connect(&mut stream, proxy, host, port)?,
+        ProxyKind::Http => http_connect(&mut stream, proxy, host, port)?,
+    }
+
+    // The SSH session applies its own timeouts from here on
+    stream
+        .set_read_timeout(None)
+        .and_then(|_| stream.set_write_timeout(None))
+        .map_err(connect_error)?;
+
+    Ok(stream)
+}
+
+// Prefix an I/O error of the negotiation, keeping its kind. Read and write
+// timeouts surface as WouldBlock on Unix and are reported as TimedOut.
+fn with_context(context: &str, error: io::Error) -> io::Error {
+    let kind = match error.kind() {
+        io::ErrorKind::WouldBlock => io::ErrorKind::TimedOut,
+        kind => kind,
+    };
+    io::Error::new(kind, format!("{}: {}", context, error))
+}
+
+// SOCKS5 CONNECT (RFC 1928) with optional username/password auth (RFC 1929).
+// Host names are sent as-is so they are resolved by the proxy.
+fn socks5_connect(
+    stream: &mut TcpStream,
+    proxy: &ProxyConfig,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let io_error = |e: io::Error| with_context("SOCKS5 proxy error", e);
+
+    let credentials = proxy.username.as_deref().map(|username| {
+        (
+            username.as_bytes(),
+            proxy.password.as_deref().unwrap_or_default().as_bytes(),
+        )
+    });
+
+    // Greeting: offer "no auth", plus "username/password" when we have credentials
+    let greeting: &[u8] = if credentials.is_some() {
+        &[0x05, 0x02, 0x00, 0x02]
+    } else {
+        &[0x05, 0x01, 0x00]
+    };
+    stream.write_all(greeting).map_err(io_error)?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).map_err(io_error)?;
+    if choice[0] != 0x05 {
+        return Err(io::Error::other(
+            "SOCKS5 proxy error: unexpected protocol version",
+        ));
+    }
+
+    match (choice[1], credentials) {
+        (0x00, _) => {}
+        (0x02, Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                return Err(io::Error::other(
+                    "SOCKS5 proxy error: username or password is too long",
+                ));
+            }
+
+            let mut request = vec![0x01, username.len() as u8];
+            request.extend_from_slice(username);
+            request.push(password.len() as u8);
+            request.extend_from_slice(password);
+            stream.write_all(&request).map_err(io_error)?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).map_err(io_error)?;
+            if status[1] != 0x00 {
+                return Err(io::Error::other("SOCKS5 proxy authentication failed"));
+            }
+        }
+        (0x02, None) => {
+            return Err(io::Error::other(
+                "SOCKS5 proxy requires a username and password",
+            ));
+        }
+        _ => {
+            return Err(io::Error::other(
+                "SOCKS5 proxy accepted none of the offered auth methods",
+            ))
+        }
+    }
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(io::Error::other(
+                    "SOCKS5 proxy error: host name is too long",
+                ));
+            }
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).map_err(io_error)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).map_err(io_error)?;
+    if reply[1] != 0x00 {
+        return Err(io::Error::other(format!(
+            "SOCKS5 proxy could not connect to {}:{}: {}",
+            host,
+            port,
+            socks5_reply_message(reply[1])
+        )));
+    }
+
+    // Skip the bound address and port
+    let address_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).map_err(io_error)?;
+            len[0] as usize
+        }
+        _ => {
+            return Err(io::Error::other(
+                "SOCKS5 proxy error: unknown address type in reply",
+            ))
+        }
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).map_err(io_error)?;
+
+    Ok(())
+}
+
+fn socks5_reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+// HTTP CONNECT tunnel with optional basic auth
+fn http_connect(
+    stream: &mut TcpStream,
+    proxy: &ProxyConfig,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let io_error = |e: io::Error| with_context("HTTP proxy error", e);
+
+    let authority = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    };
+
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+    if let Some(username) = &proxy.username {
+        let credentials = format!(
+            "{}:{}",
+            username,
+            proxy.password.as_deref().unwrap_or_default()
+        );
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            STANDARD.encode(credentials)
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).map_err(io_error)?;
+
+    // Read byte by byte so nothing past the headers (the SSH banner) is consumed
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_HTTP_RESPONSE_HEADERS {
+            return Err(io::Error::other(
+                "HTTP proxy error: response headers too large",
+            ));
+        }
+        stream.read_exact(&mut byte).map_err(io_error)?;
+        response.push(byte[0]);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| {
+            io::Error::other(format!(
+                "HTTP proxy error: invalid response '{}'",
+                status_line
+            ))
+        })?;
+
+    match status {
+        200..=299 => Ok(()),
+        407 => Err(io::Error::other("HTTP proxy authentication failed")),
+        _ => Err(io::Error::other(format!(
+            "HTTP proxy could not connect to {}: {}",
+            authority, status_line
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn proxy_config(kind: ProxyKind, port: u16, credentials: Option<(&str, &str)>) -> ProxyConfig {
+        ProxyConfig {
+            kind,
+            host: "127.0.0.1".to_string(),
+            port,
+            username: credentials.map(|(username, _)| username.to_string()),
+            password: credentials.map(|(_, password)| password.to_string()),
+        }
+    }
+
+    // Minimal SOCKS5 stand-in: checks the negotiation, then echoes one line back
+    fn spawn_socks5_proxy(credentials: Option<(&'static str, &'static str)>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || serve_socks5(listener, credentials));
+        port
+    }
+
+    fn serve_socks5(
+        listener: TcpListener,
+        credentials: Option<(&str, &str)>,
+    ) -> std::io::Result<()> {
+        let (mut client, _) = listener.accept()?;
+
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header)?;
+        let mut methods = vec![0u8; header[1] as usize];
+        client.read_exact(&mut methods)?;
+
+        if let Some((username, password)) = credentials {
+            client.write_all(&[0x05, 0x02])?;
+            let mut version_and_len = [0u8; 2];
+            client.read_exact(&mut version_and_len)?;
+            let mut user = vec![0u8; version_and_len[1] as usize];
+            client.read_exact(&mut user)?;
+            let mut len = [0u8; 1];
+            client.read_exact(&mut len)?;
+            let mut pass = vec![0u8; len[0] as usize];
+            client.read_exact(&mut pass)?;
+
+            if user != username.as_bytes() || pass != password.as_bytes() {
+                return client.write_all(&[0x01, 0x01]);
+            }
+            client.write_all(&[0x01, 0x00])?;
+        } else {
+            client.write_all(&[0x05, 0x00])?;
+        }
+
+        let mut request = [0u8; 5];
+        client.read_exact(&mut request)?;
+        let mut host = vec![0u8; request[4] as usize];
+        client.read_exact(&mut host)?;
+        let mut target_port = [0u8; 2];
+        client.read_exact(&mut target_port)?;
+
+        if request[..4] != [0x05, 0x01, 0x00, 0x03]
+            || host != b"target.internal"
+            || u16::from_be_bytes(target_port) != 2222
+        {
+            return client.write_all(&[0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+        }
+
+        client.write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x1f, 0x90])?;
+        echo_line(client)
+    }
+
+    // Minimal HTTP CONNECT stand-in: checks the request, then echoes one line back
+    fn spawn_http_proxy(expected_auth: Option<&'static str>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || serve_http(listener, expected_auth));
+        port
+    }
+
+    fn serve_http(listener: TcpListener, expected_auth: Option<&str>) -> std::io::Result<()> {
+        let (mut client, _) = listener.accept()?;
+        let mut reader = BufReader::new(client.try_clone()?);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        let mut authorization = None;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Proxy-Authorization: ") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+
+        if request_line != "CONNECT target.internal:2222 HTTP/1.1\r\n" {
+            return client.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        }
+        if authorization.as_deref() != expected_auth {
+            return client.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n");
+        }
+
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+        echo_line(client)
+    }
+
+    fn echo_line(mut client: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(client.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        client.write_all(line.as_bytes())
+    }
+
+    fn assert_tunnel_works(mut stream: TcpStream) {
+        stream.write_all(b"SSH-2.0-test\r\n").unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "SSH-2.0-test\r\n");
+    }
+
+    #[test]
+    fn socks5_without_auth() {
+        let port = spawn_socks5_proxy(None);
+        let proxy = proxy_config(ProxyKind::Socks5, port, None);
+        let stream = connect_via_proxy(&proxy, "target.internal", 2222, TIMEOUT).unwrap();
+        assert_tunnel_works(stream);
+    }
+
+    #[test]
+    fn socks5_with_auth() {
+        let port = spawn_socks5_proxy(Some(("alice", "secret")));
+        let proxy = proxy_config(ProxyKind::Socks5, port, Some(("alice", "secret")));
+        let stream = connect_via_proxy(&proxy, "target.internal", 2222, TIMEOUT).unwrap();
+        assert_tunnel_works(stream);
+    }
+
+    #[test]
+    fn socks5_rejects_bad_credentials() {
+        let port = spawn_socks5_proxy(Some(("alice", "secret")));
+        let proxy = proxy_config(ProxyKind::Socks5, port, Some(("alice", "wrong")));
+        let error = connect_via_proxy(&proxy, "target.internal", 2222, TIMEOUT).unwrap_err();
+        assert_eq!(error.to_string(), "SOCKS5 proxy authentication failed");
+    }
+
+    #[test]
+    fn socks5_requires_credentials_when_asked() {
+        let port = spawn_socks5_proxy(Some(("alice", "secret")));
+        let proxy = proxy_config(ProxyKind::Socks5, port, None);
+        let error = connect_via_proxy(&proxy, "target.internal", 2222, TIMEOUT).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "SOCKS5 proxy requires a username and password"
+        );
+    }
+
+    #[test]
+    fn http_connect_without_auth() {
+        let port = spawn_http_proxy(None);
+        let proxy = proxy_config(ProxyKind::Http, port, None);
+        let stream = connect_via_proxy(&proxy, "target.internal", 2222, TIMEOUT).unwrap();
+        assert_tunnel_works(stream);
+    }
+
+    #[test]
+    fn http_connect_with_basic_auth() {
+        // base64("alice:secret")
+        let port = spawn_http_proxy(Some("Basic YWxpY2U6c2VjcmV0"));
+        let proxy = proxy_config(ProxyKind::Http, port, Some(("alice", "secret")));
+        let stream = connect_via_proxy(&proxy, "target.internal", 2222, TIMEOUT).unwrap();
+        assert_tunnel_works(stream);
+    }
+
+    #[test]
+    fn silent_proxy_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Accept and hold the connection without ever answering
+        thread::spawn(move || {
+            let client = listener.accept();
+            thread::sleep(TIMEOUT);
+            drop(client);
+        });
+
+        let proxy = proxy_config(ProxyKind::Socks5, port, None);
+        let timeout = Duration::from_millis(200);
+        let error = connect_via_proxy(&proxy, "target.internal", 2222, timeout).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn http_connect_reports_rejected_auth() {
+        let port = spawn_http_proxy(Some("Basic YWxpY2U6c2VjcmV0"));
+        let proxy = proxy_config(ProxyKind::Http, port, Some(("alice", "wrong")));
+        let error = connect_via_proxy(&proxy, "target.internal", 2222, TIMEOUT).unwrap_err();
+        assert_eq!(error.to_string(), "HTTP proxy authentication failed");
+    }
+}
@@ -0,0 +1,553 @@
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::sftp::file_operations::{TransferControl, TRANSFER_CANCEL_MAP};
+use crate::sftp::transfers::remove_record;
+use crate::types::*;
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+
+// Transfers running at once across all connections, unless set_transfer_limits says otherwise
+const DEFAULT_MAX_TRANSFERS: usize = 4;
+// Transfers running at once on one connection; ConnectionConfig::max_transfers overrides it
+const DEFAULT_MAX_TRANSFERS_PER_CONNECTION: usize = 2;
+
+// Uploads, downloads and copies waiting for a free slot, running, or finished.
+// Waiting transfers start in list order as slots free up, so the front of the
+// list has priority. Finished ones stay listed until removed, so the frontend
+// can render every transfer from transfer_queue_changed alone.
+pub struct TransferQueue {
+    inner: Mutex<QueueInner>,
+}
+
+struct QueueInner {
+    entries: Vec<QueueEntry>,
+    max_transfers: usize,
+    max_transfers_per_connection: usize,
+}
+
+struct QueueEntry {
+    transfer: QueuedTransfer,
+    control: TransferControl,
+    job: Option<BoxFuture<'static, Result<(), SftpError>>>, // Taken when the transfer starts
+}
+
+impl Default for TransferQueue {
+    fn default() -> Self {
+        TransferQueue {
+            inner: Mutex::new(QueueInner {
+                entries: Vec::new(),
+                max_transfers: DEFAULT_MAX_TRANSFERS,
+                max_transfers_per_connection: DEFAULT_MAX_TRANSFERS_PER_CONNECTION,
+            }),
+        }
+    }
+}
+
+impl TransferQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Queue a transfer whose work is `job`, starting it right away if a slot is
+    // free. `control` must be registered in TRANSFER_CANCEL_MAP under the
+    // transfer id, so it can be cancelled and paused while it waits.
+    pub fn enqueue<F>(
+        &self,
+        app: &AppHandle,
+        transfer: QueuedTransfer,
+        control: TransferControl,
+        job: F,
+    ) where
+        F: Future<Output = Result<(), SftpError>> + Send + 'static,
+    {
+        let entry = QueueEntry {
+            transfer,
+            control,
+            job: Some(Box::pin(job)),
+        };
+        {
+            let mut inner = self.inner.lock().unwrap();
+            // A failed transfer that is resumed keeps its id and its place
+            match inner.find_mut(&entry.transfer.transfer_id) {
+                Some(existing) => *existing = entry,
+                None => inner.entries.push(entry),
+            }
+        }
+        self.schedule(app);
+    }
+
+    // Start waiting transfers in list order while there are free slots. Paused
+    // transfers are passed over until they are resumed.
+    pub fn schedule(&self, app: &AppHandle) {
+        let mut started = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let connections = app.state::<ConnectionManager>();
+            let startable = inner.startable(|connection_id| {
+                connections
+                    .config(connection_id)
+                    .and_then(|config| config.max_transfers)
+            });
+
+            for index in startable {
+                let entry = &mut inner.entries[index];
+                entry.transfer.state = QueueState::Running;
+                if let Some(job) = entry.job.take() {
+                    started.push((entry.transfer.transfer_id.clone(), job));
+                }
+            }
+        }
+
+        for (transfer_id, job) in started {
+            let app = app.clone();
+            tokio::spawn(async move {
+                let result = job.await;
+                app.state::<TransferQueue>()
+                    .finish(&app, &transfer_id, result);
+            });
+        }
+        self.emit_changed(app);
+    }
+
+    // Record how a transfer ended and start the next ones
+    fn finish(&self, app: &AppHandle, transfer_id: &str, result: Result<(), SftpError>) {
+        // Transfers that fail before they get far leave their registration behind
+        TRANSFER_CANCEL_MAP.lock().unwrap().remove(transfer_id);
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(entry) = inner.find_mut(transfer_id) {
+                let cancelled = entry.control.cancel_flag.load(Ordering::Relaxed);
+                let (state, error) = match result {
+                    Ok(()) if cancelled => (QueueState::Cancelled, None),
+                    Ok(()) => (QueueState::Completed, None),
+                    Err(error) if error.kind == ErrorKind::Cancelled => {
+                        (QueueState::Cancelled, None)
+                    }
+                    Err(error) => (QueueState::Failed, Some(error)),
+                };
+                entry.transfer.state = state;
+                entry.transfer.error = error;
+            }
+        }
+        self.schedule(app);
+    }
+
+    // Cancel a transfer that hasn't started. Returns false when it isn't waiting,
+    // so a running one is cancelled through its flag instead.
+    pub fn cancel_queued(&self, app: &AppHandle, transfer_id: &str) -> bool {
+        let kind = {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.find_mut(transfer_id) {
+                Some(entry) if entry.transfer.state == QueueState::Queued => {
+                    entry.transfer.state = QueueState::Cancelled;
+                    entry.job = None;
+                    entry.transfer.kind
+                }
+                _ => return false,
+            }
+        };
+        TRANSFER_CANCEL_MAP.lock().unwrap().remove(transfer_id);
+        // Uploads and downloads are recorded as resumable when they are queued
+        remove_record(app, transfer_id).ok();
+
+        app.emit(
+            "transfer_cancelled",
+            serde_json::json!({
+                "transfer_id": transfer_id,
+                "type": kind
+            }),
+        )
+        .ok();
+        self.emit_changed(app);
+        true
+    }
+
+    fn snapshot(&self) -> Vec<QueuedTransfer> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut transfer = entry.transfer.clone();
+                let active = matches!(transfer.state, QueueState::Queued | QueueState::Running);
+                if active && entry.control.pause_flag.load(Ordering::Relaxed) {
+                    transfer.state = QueueState::Paused;
+                }
+                transfer
+            })
+            .collect()
+    }
+
+    // Emit transfer_queue_changed with every listed transfer in queue order
+    pub fn emit_changed(&self, app: &AppHandle) {
+        app.emit("transfer_queue_changed", self.snapshot()).ok();
+    }
+}
+
+impl QueuedTransfer {
+    // A transfer that is waiting to start
+    pub fn new(transfer_id: &str, connection_id: &str, kind: TransferKind, path: &str) -> Self {
+        QueuedTransfer {
+            transfer_id: transfer_id.to_string(),
+            connection_id: connection_id.to_string(),
+            kind,
+            path: path.to_string(),
+            state: QueueState::Queued,
+            error: None,
+        }
+    }
+}
+
+impl QueueInner {
+    // Indices of the waiting transfers that can start now, front to back.
+    // `connection_limit` gives a connection's own limit, if it has one. Paused
+    // transfers neither start nor take up a slot; one that is resumed continues
+    // even when that puts its connection over the limit for a while.
+    fn startable<F>(&self, connection_limit: F) -> Vec<usize>
+    where
+        F: Fn(&str) -> Option<usize>,
+    {
+        let paused = |entry: &QueueEntry| entry.control.pause_flag.load(Ordering::Relaxed);
+        let mut running: HashMap<&str, usize> = HashMap::new();
+        for entry in &self.entries {
+            if entry.transfer.state == QueueState::Running && !paused(entry) {
+                *running.entry(&entry.transfer.connection_id).or_default() += 1;
+            }
+        }
+        let mut total: usize = running.values().sum();
+
+        let mut startable = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            if total >= self.max_transfers {
+                break;
+            }
+            if entry.transfer.state != QueueState::Queued || paused(entry) {
+                continue;
+            }
+
+            let connection_id = entry.transfer.connection_id.as_str();
+            let limit =
+                connection_limit(connection_id).unwrap_or(self.max_transfers_per_connection);
+            let count = running.entry(connection_id).or_default();
+            if *count >= limit {
+                continue;
+            }
+
+            *count += 1;
+            total += 1;
+            startable.push(index);
+        }
+        startable
+    }
+
+    // Move a transfer to `position`, or to the back when that's past the end
+    fn move_to(&mut self, transfer_id: &str, position: usize) -> Result<(), SftpError> {
+        let from = self.position(transfer_id)?;
+        let entry = self.entries.remove(from);
+        let to = position.min(self.entries.len());
+        self.entries.insert(to, entry);
+        Ok(())
+    }
+
+    fn find_mut(&mut self, transfer_id: &str) -> Option<&mut QueueEntry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.transfer.transfer_id == transfer_id)
+    }
+
+    fn position(&self, transfer_id: &str) -> Result<usize, SftpError> {
+        self.entries
+            .iter()
+            .position(|entry| entry.transfer.transfer_id == transfer_id)
+            .ok_or_else(|| {
+                SftpError::new(
+                    ErrorKind::NotFound,
+                    format!("Transfer with ID {} is not in the queue", transfer_id),
+                )
+            })
+    }
+}
+
+// Every transfer in the queue, in queue order
+#[tauri::command]
+pub async fn get_transfer_queue(app: AppHandle) -> Result<Vec<QueuedTransfer>, SftpError> {
+    Ok(app.state::<TransferQueue>().snapshot())
+}
+
+// Set how many transfers run at once overall and per connection. A connection's
+// own max_transfers takes precedence over the per-connection default.
+#[tauri::command]
+pub async fn set_transfer_limits(
+    max_transfers: Option<usize>,
+    max_transfers_per_connection: Option<usize>,
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    if max_transfers == Some(0) || max_transfers_per_connection == Some(0) {
+        return Err(SftpError::new(
+            ErrorKind::InvalidInput,
+            "Transfer limits must be at least 1",
+        ));
+    }
+
+    let queue = app.state::<TransferQueue>();
+    {
+        let mut inner = queue.inner.lock().unwrap();
+        if let Some(max_transfers) = max_transfers {
+            inner.max_transfers = max_transfers;
+        }
+        if let Some(max_transfers_per_connection) = max_transfers_per_connection {
+            inner.max_transfers_per_connection = max_transfers_per_connection;
+        }
+    }
+    // Raised limits take effect right away; lowered ones as running transfers finish
+    queue.schedule(&app);
+    Ok(())
+}
+
+// Move a transfer to `position` in the queue (0 is the front). Only the order of
+// waiting transfers matters; they start front to back.
+#[tauri::command]
+pub async fn move_transfer(
+    transfer_id: String,
+    position: usize,
+    app: AppHandle,
+) -> Result<(), SftpError> {
+    let queue = app.state::<TransferQueue>();
+    queue
+        .inner
+        .lock()
+        .unwrap()
+        .move_to(&transfer_id, position)?;
+    queue.emit_changed(&app);
+    Ok(())
+}
+
+// Move a waiting transfer to the front, so it starts in the next free slot
+#[tauri::command]
+pub async fn prioritize_transfer(transfer_id: String, app: AppHandle) -> Result<(), SftpError> {
+    move_transfer(transfer_id, 0, app).await
+}
+
+// Take a transfer off the list: a waiting one is dropped without running, a
+// finished one is cleared. Running transfers have to be cancelled first.
+#[tauri::command]
+pub async fn remove_transfer(transfer_id: String, app: AppHandle) -> Result<(), SftpError> {
+    let queue = app.state::<TransferQueue>();
+    {
+        let mut inner = queue.inner.lock().unwrap();
+        let index = inner.position(&transfer_id)?;
+        if inner.entries[index].transfer.state == QueueState::Running {
+            return Err(SftpError::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Transfer with ID {} is running; cancel it first",
+                    transfer_id
+                ),
+            )
+            .with_path(&inner.entries[index].transfer.path));
+        }
+        let entry = inner.entries.remove(index);
+        // A transfer that never started leaves nothing to resume; a failed one
+        // stays resumable through get_resumable_transfers
+        if entry.transfer.state == QueueState::Queued {
+            remove_record(&app, &transfer_id).ok();
+        }
+    }
+    TRANSFER_CANCEL_MAP.lock().unwrap().remove(&transfer_id);
+
+    queue.emit_changed(&app);
+    Ok(())
+}
+
+// Clear every finished transfer from the list
+#[tauri::command]
+pub async fn clear_finished_transfers(app: AppHandle) -> Result<(), SftpError> {
+    let queue = app.state::<TransferQueue>();
+    queue.inner.lock().unwrap().entries.retain(|entry| {
+        matches!(
+            entry.transfer.state,
+            QueueState::Queued | QueueState::Running
+        )
+    });
+    queue.emit_changed(&app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Queue with the given limits and one entry per (id, connection, state)
+    fn queue(
+        max_transfers: usize,
+        max_transfers_per_connection: usize,
+        entries: &[(&str, &str, QueueState)],
+    ) -> QueueInner {
+        QueueInner {
+            entries: entries
+                .iter()
+                .map(|(transfer_id, connection_id, state)| QueueEntry {
+                    transfer: QueuedTransfer {
+                        state: *state,
+                        ..QueuedTransfer::new(
+                            transfer_id,
+                            connection_id,
+                            TransferKind::Upload,
+                            "/f",
+                        )
+                    },
+                    control: TransferControl::default(),
+                    job: None,
+                })
+                .collect(),
+            max_transfers,
+            max_transfers_per_connection,
+        }
+    }
+
+    fn pause(queue: &QueueInner, transfer_id: &str) {
+        let index = queue.position(transfer_id).unwrap();
+        queue.entries[index]
+            .control
+            .pause_flag
+            .store(true, Ordering::Relaxed);
+    }
+
+    fn ids(queue: &QueueInner) -> Vec<&str> {
+        queue
+            .entries
+            .iter()
+            .map(|entry| entry.transfer.transfer_id.as_str())
+            .collect()
+    }
+
+    fn no_override(_: &str) -> Option<usize> {
+        None
+    }
+
+    #[test]
+    fn starts_in_list_order_up_to_the_global_limit() {
+        let queue = queue(
+            2,
+            5,
+            &[
+                ("a", "c1", QueueState::Queued),
+                ("b", "c2", QueueState::Queued),
+                ("c", "c3", QueueState::Queued),
+            ],
+        );
+        assert_eq!(queue.startable(no_override), vec![0, 1]);
+    }
+
+    #[test]
+    fn running_transfers_take_up_slots() {
+        let queue = queue(
+            2,
+            5,
+            &[
+                ("a", "c1", QueueState::Running),
+                ("b", "c1", QueueState::Queued),
+                ("c", "c1", QueueState::Queued),
+            ],
+        );
+        assert_eq!(queue.startable(no_override), vec![1]);
+    }
+
+    #[test]
+    fn per_connection_limit_lets_other_connections_through() {
+        let queue = queue(
+            4,
+            1,
+            &[
+                ("a", "c1", QueueState::Running),
+                ("b", "c1", QueueState::Queued),
+                ("c", "c2", QueueState::Queued),
+                ("d", "c2", QueueState::Queued),
+            ],
+        );
+        assert_eq!(queue.startable(no_override), vec![2]);
+    }
+
+    #[test]
+    fn connection_limit_overrides_the_default() {
+        let queue = queue(
+            4,
+            1,
+            &[
+                ("a", "c1", QueueState::Queued),
+                ("b", "c1", QueueState::Queued),
+                ("c", "c1", QueueState::Queued),
+                ("d", "c2", QueueState::Queued),
+            ],
+        );
+        let limit = |connection_id: &str| (connection_id == "c1").then_some(2);
+        assert_eq!(queue.startable(limit), vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn paused_transfers_neither_start_nor_take_up_slots() {
+        let queue = queue(
+            4,
+            2,
+            &[
+                ("a", "c1", QueueState::Running),
+                ("b", "c1", QueueState::Running),
+                ("c", "c1", QueueState::Queued),
+                ("d", "c1", QueueState::Queued),
+                ("e", "c1", QueueState::Queued),
+            ],
+        );
+        pause(&queue, "a");
+        pause(&queue, "b");
+        pause(&queue, "c");
+        assert_eq!(queue.startable(no_override), vec![3, 4]);
+    }
+
+    #[test]
+    fn finished_transfers_are_passed_over() {
+        let queue = queue(
+            1,
+            1,
+            &[
+                ("a", "c1", QueueState::Completed),
+                ("b", "c1", QueueState::Failed),
+                ("c", "c1", QueueState::Cancelled),
+                ("d", "c1", QueueState::Queued),
+            ],
+        );
+        assert_eq!(queue.startable(no_override), vec![3]);
+    }
+
+    #[test]
+    fn moves_transfers_and_clamps_the_position() {
+        let mut queue = queue(
+            1,
+            1,
+            &[
+                ("a", "c1", QueueState::Queued),
+                ("b", "c1", QueueState::Queued),
+                ("c", "c1", QueueState::Queued),
+            ],
+        );
+        queue.move_to("c", 0).unwrap();
+        assert_eq!(ids(&queue), vec!["c", "a", "b"]);
+        queue.move_to("c", 99).unwrap();
+        assert_eq!(ids(&queue), vec!["a", "b", "c"]);
+        queue.move_to("a", 1).unwrap();
+        assert_eq!(ids(&queue), vec!["b", "a", "c"]);
+        // A moved transfer is the next to start
+        queue.move_to("c", 0).unwrap();
+        assert_eq!(queue.startable(no_override), vec![0]);
+    }
+
+    #[test]
+    fn moving_an_unknown_transfer_fails() {
+        let mut queue = queue(1, 1, &[("a", "c1", QueueState::Queued)]);
+        let error = queue.move_to("missing", 0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NotFound);
+        assert_eq!(ids(&queue), vec!["a"]);
+    }
+}
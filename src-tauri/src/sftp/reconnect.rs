@@ -0,0 +1,229 @@
+use crate::sftp::connection::establish_connection;
+use crate::sftp::engine::SftpEngine;
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::sftp::pool::SftpPool;
+use crate::sftp::utils::run_blocking;
+use crate::types::*;
+use ssh2::Sftp;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+// SFTP channel pool of a connection's current session along with its generation
+pub fn current_pool(
+    app: &AppHandle,
+    connection_id: &str,
+) -> Result<(Arc<SftpPool>, u64), SftpError> {
+    app.state::<ConnectionManager>()
+        .sftp_pool(connection_id)
+        .ok_or_else(SftpError::connection_not_found)
+}
+
+// Current SFTP engine of a connection along with its generation
+pub fn current_engine(
+    app: &AppHandle,
+    connection_id: &str,
+) -> Result<(SftpEngine, u64), SftpError> {
+    app.state::<ConnectionManager>()
+        .engine(connection_id)
+        .ok_or_else(SftpError::connection_not_found)
+}
+
+// Like with_reconnect, for operations that go through the connection's SFTP engine
+pub async fn with_engine<T, F, Fut>(
+    app: &AppHandle,
+    connection_id: &str,
+    idempotent: bool,
+    operation: F,
+) -> Result<T, SftpError>
+where
+    F: Fn(SftpEngine) -> Fut,
+    Fut: Future<Output = Result<T, SftpError>>,
+{
+    let (engine, generation) = current_engine(app, connection_id)?;
+
+    match operation(engine).await {
+        Err(error) if error.transport_lost => {
+            reconnect(app, connection_id, generation)
+                .await
+                .map_err(|e| error.clone().with_cause(&e))?;
+
+            if idempotent {
+                let (engine, _) = current_engine(app, connection_id)?;
+                operation(engine).await
+            } else {
+                Err(error)
+            }
+        }
+        result => result,
+    }
+}
+
+// Run an operation on a pooled SFTP channel of the connection, on the blocking
+// thread pool. If the transport turns out to be gone, the session is
+// re-established under the same connection id and, for idempotent operations,
+// the operation is run once more on the new session.
+pub async fn with_reconnect<T, F>(
+    app: &AppHandle,
+    connection_id: &str,
+    idempotent: bool,
+    operation: F,
+) -> Result<T, SftpError>
+where
+    F: Fn(&Sftp) -> Result<T, SftpError> + Send + Sync + 'static,
+    T: Send + 'static,
+{
+    let operation = Arc::new(operation);
+    let (pool, generation) = current_pool(app, connection_id)?;
+
+    let result = run_blocking({
+        let operation = operation.clone();
+        move || pool.with_channel(|sftp| operation(sftp))
+    })
+    .await?;
+
+    match result {
+        Err(error) if error.transport_lost => {
+            let (pool, _) = reconnect(app, connection_id, generation)
+                .await
+                .map_err(|e| error.clone().with_cause(&e))?;
+
+            if idempotent {
+                run_blocking(move || pool.with_channel(|sftp| operation(sftp))).await?
+            } else {
+                Err(error)
+            }
+        }
+        result => result,
+    }
+}
+
+// Reconnect after a non-idempotent operation failed, without retrying it.
+// Returns the original error for the caller to report.
+pub async fn reconnect_after(
+    app: &AppHandle,
+    connection_id: &str,
+    generation: u64,
+    error: SftpError,
+) -> SftpError {
+    if error.transport_lost {
+        reconnect(app, connection_id, generation).await.ok();
+    }
+    error
+}
+
+// Re-establish a dropped session with exponential backoff, keeping the connection id.
+// Reconnects of one connection run one at a time; if another task already
+// replaced the session of this generation, that one is used.
+// Rejected credentials or host keys end the attempts at once. When the
+// reconnect fails the connection is dropped and connection_lost is emitted
+// with the error of the last attempt.
+pub async fn reconnect(
+    app: &AppHandle,
+    connection_id: &str,
+    generation: u64,
+) -> Result<(Arc<SftpPool>, u64), SftpError> {
+    let connection = app
+        .state::<ConnectionManager>()
+        .get(connection_id)
+        .ok_or_else(SftpError::connection_not_found)?;
+    let reconnecting = connection.lock().unwrap().reconnecting.clone();
+    let _reconnecting = reconnecting.lock().await;
+
+    let config = {
+        let connection = connection.lock().unwrap();
+        if connection.generation != generation {
+            return Ok((connection.sftp_pool.clone(), connection.generation));
+        }
+        connection.config.clone()
+    };
+
+    let mut delay = INITIAL_RECONNECT_DELAY;
+    let mut attempt = 0;
+
+    let error = loop {
+        attempt += 1;
+        app.emit(
+            "connection_reconnecting",
+            serde_json::json!({
+                "connection_id": connection_id,
+                "attempt": attempt,
+                "max_attempts": MAX_RECONNECT_ATTEMPTS
+            }),
+        )
+        .ok();
+
+        match establish_connection(&config, connection_id, app).await {
+            Ok(established) => {
+                let session = established.session.clone();
+                let replaced = app
+                    .state::<ConnectionManager>()
+                    .replace_session(connection_id, established);
+
+                // The user disconnected while we were reconnecting
+                let (pool, generation) = match replaced {
+                    Some(replaced) => replaced,
+                    None => {
+                        tokio::task::spawn_blocking(move || {
+                            session.disconnect(None, "User disconnected", None).ok();
+                        });
+                        return Err(SftpError::connection_not_found());
+                    }
+                };
+
+                app.emit(
+                    "connection_reconnected",
+                    serde_json::json!({
+                        "connection_id": connection_id,
+                        "attempt": attempt
+                    }),
+                )
+                .ok();
+
+                return Ok((pool, generation));
+            }
+            // Another attempt can't fix rejected credentials or host keys
+            Err(e) if !retryable(&e) || attempt == MAX_RECONNECT_ATTEMPTS => break e,
+            Err(_) => {}
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    };
+
+    let lost = app.state::<ConnectionManager>().remove(connection_id);
+    if let Some(session) = lost {
+        tokio::task::spawn_blocking(move || {
+            session.disconnect(None, "Connection lost", None).ok();
+        });
+    }
+
+    app.emit(
+        "connection_lost",
+        serde_json::json!({
+            "connection_id": connection_id,
+            "error": error
+        }),
+    )
+    .ok();
+
+    Err(SftpError::new(
+        ErrorKind::ConnectionLost,
+        format!(
+            "Reconnect failed after {} attempts: {}",
+            attempt, error.message
+        ),
+    ))
+}
+
+fn retryable(error: &SftpError) -> bool {
+    !matches!(
+        error.kind,
+        ErrorKind::AuthFailed | ErrorKind::HostKeyChanged | ErrorKind::HostKeyUnknown
+    )
+}
@@ -0,0 +1,706 @@
+use crate::sftp::error::SftpError;
+use crate::types::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::{Manager, Window};
+
+// Same nesting limit OpenSSH applies to Include
+const MAX_INCLUDE_DEPTH: u32 = 16;
+// ProxyJump hops are resolved through the config as well, guard against cycles
+const MAX_JUMP_DEPTH: u32 = 8;
+
+// List the concrete Host aliases of ~/.ssh/config (wildcard patterns are skipped),
+// each resolved into the connection config OpenSSH would use for it
+#[tauri::command]
+pub async fn list_ssh_config_hosts(window: Window) -> Result<Vec<SshConfigHost>, SftpError> {
+    let home = home_dir(&window)?;
+    let config_path = home.join(".ssh").join("config");
+
+    let mut aliases = Vec::new();
+    collect_aliases(&config_path, &home, 0, &mut aliases)?;
+
+    aliases
+        .into_iter()
+        .map(|alias| {
+            let config = resolve_host(&config_path, &home, &alias)?;
+            Ok(SshConfigHost { alias, config })
+        })
+        .collect()
+}
+
+// Resolve a single alias from ~/.ssh/config into a connection config
+#[tauri::command]
+pub async fn resolve_ssh_config_host(
+    alias: String,
+    window: Window,
+) -> Result<ConnectionConfig, SftpError> {
+    let home = home_dir(&window)?;
+    Ok(resolve_host(
+        &home.join(".ssh").join("config"),
+        &home,
+        &alias,
+    )?)
+}
+
+fn home_dir(window: &Window) -> Result<PathBuf, String> {
+    window
+        .app_handle()
+        .path()
+        .home_dir()
+        .map_err(|e| format!("Failed to find home directory: {}", e))
+}
+
+// Resolve `alias` against the config file at `config_path` the way OpenSSH does:
+// every matching Host/Match block contributes, and the first value seen wins
+pub fn resolve_host(
+    config_path: &Path,
+    home: &Path,
+    alias: &str,
+) -> Result<ConnectionConfig, String> {
+    resolve_host_depth(config_path, home, alias, 0)
+}
+
+fn resolve_host_depth(
+    config_path: &Path,
+    home: &Path,
+    alias: &str,
+    jump_depth: u32,
+) -> Result<ConnectionConfig, String> {
+    let mut resolver = Resolver {
+        home,
+        alias,
+        local_user: local_user(),
+        options: HashMap::new(),
+    };
+    resolver.apply_file(config_path, 0)?;
+
+    let port = match resolver.first("port") {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format!("Invalid Port '{}' for host {}", port, alias))?,
+        None => 22,
+    };
+    let username = resolver
+        .first("user")
+        .map(str::to_string)
+        .unwrap_or_else(|| resolver.local_user.clone());
+
+    // %h in HostName refers to the alias that was asked for
+    let mut tokens = Tokens {
+        hostname: alias,
+        alias,
+        port,
+        remote_user: &username,
+        local_user: &resolver.local_user,
+        home,
+    };
+    let hostname = match resolver.first("hostname") {
+        Some(hostname) => expand_tokens(hostname, &tokens),
+        None => alias.to_string(),
+    };
+    tokens.hostname = &hostname;
+
+    // A connection holds a single key, so only the first IdentityFile is used;
+    // OpenSSH would offer every one in turn
+    let private_key_path = resolver
+        .first("identityfile")
+        .filter(|path| !path.eq_ignore_ascii_case("none"))
+        .map(|path| expand_path(&expand_tokens(path, &tokens), home));
+
+    let use_agent = !resolver
+        .first("identityagent")
+        .map(|agent| agent.eq_ignore_ascii_case("none"))
+        .unwrap_or(false);
+
+    let connect_timeout_secs = match resolver.first("connecttimeout") {
+        Some(timeout) => Some(
+            timeout
+                .parse()
+                .map_err(|_| format!("Invalid ConnectTimeout '{}' for host {}", timeout, alias))?,
+        ),
+        None => None,
+    };
+
+    let jump_hosts = match resolver.first("proxyjump") {
+        Some(jumps) if !jumps.eq_ignore_ascii_case("none") => {
+            if jump_depth >= MAX_JUMP_DEPTH {
+                return Err(format!("ProxyJump chain for {} is too deep", alias));
+            }
+            let hops = jumps
+                .split(',')
+                .map(|hop| resolve_jump_host(config_path, home, hop.trim(), jump_depth + 1))
+                .collect::<Result<Vec<_>, String>>()?;
+            Some(hops)
+        }
+        _ => None,
+    };
+
+    Ok(ConnectionConfig {
+        host: hostname,
+        port,
+        username,
+        password: None,
+        private_key_path,
+        passphrase: None,
+        use_agent: Some(use_agent),
+        agent_identity: None,
+        jump_hosts,
+        proxy: None,
+        connect_timeout_secs,
+        handshake_timeout_secs: None,
+        operation_timeout_secs: None,
+        sftp_backend: None,
+        show_hidden: None,
+        max_transfers: None,
+    })
+}
+
+// A ProxyJump hop is "[user@]host[:port]" or "ssh://[user@]host[:port]", where host
+// may itself be an alias from the config
+fn resolve_jump_host(
+    config_path: &Path,
+    home: &Path,
+    hop: &str,
+    jump_depth: u32,
+) -> Result<ConnectionConfig, String> {
+    let hop = hop.trim_start_matches("ssh://");
+    let (user, host_port) = match hop.rfind('@') {
+        Some(index) => (Some(&hop[..index]), &hop[index + 1..]),
+        None => (None, hop),
+    };
+    let (host, port) = split_host_port(host_port)
+        .filter(|(host, _)| !host.is_empty())
+        .ok_or(format!("Invalid ProxyJump host '{}'", hop))?;
+
+    let mut config = resolve_host_depth(config_path, home, host, jump_depth)?;
+    if let Some(user) = user {
+        config.username = user.to_string();
+    }
+    if let Some(port) = port {
+        config.port = port;
+    }
+    Ok(config)
+}
+
+fn split_host_port(value: &str) -> Option<(&str, Option<u16>)> {
+    // [ipv6]:port or [ipv6]
+    if let Some(rest) = value.strip_prefix('[') {
+        let end = rest.find(']')?;
+        let port = match &rest[end + 1..] {
+            "" => None,
+            port => Some(port.strip_prefix(':')?.parse().ok()?),
+        };
+        return Some((&rest[..end], port));
+    }
+
+    match value.rfind(':') {
+        Some(index) if value[..index].find(':').is_none() => {
+            Some((&value[..index], Some(value[index + 1..].parse().ok()?)))
+        }
+        _ => Some((value, None)),
+    }
+}
+
+struct Resolver<'a> {
+    home: &'a Path,
+    alias: &'a str,
+    local_user: String,
+    options: HashMap<String, String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn first(&self, keyword: &str) -> Option<&str> {
+        self.options.get(keyword).map(String::as_str)
+    }
+
+    fn apply_file(&mut self, path: &Path, depth: u32) -> Result<(), String> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "Too many nested Include directives at {}",
+                path.display()
+            ));
+        }
+
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        // Directives before the first Host/Match apply to every host
+        let mut active = true;
+
+        for line in content.lines() {
+            let (keyword, args) = match parse_line(line) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+
+            match keyword.as_str() {
+                "host" => active = host_matches(&args, self.alias),
+                "match" => active = self.match_matches(&args),
+                "include" if active => {
+                    for pattern in &args {
+                        for file in expand_include(pattern, self.home) {
+                            self.apply_file(&file, depth + 1)?;
+                        }
+                    }
+                }
+                _ if active => {
+                    if let Some(value) = args.into_iter().next() {
+                        // First obtained value wins
+                        self.options.entry(keyword).or_insert(value);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // Evaluate "Match criterion [args] ..." where every criterion must hold.
+    // Criteria we cannot evaluate locally (exec, localnetwork, ...) never match.
+    fn match_matches(&self, args: &[String]) -> bool {
+        let mut args = args.iter();
+
+        while let Some(criterion) = args.next() {
+            let criterion = criterion.to_lowercase();
+            let (negated, criterion) = match criterion.strip_prefix('!') {
+                Some(criterion) => (true, criterion),
+                None => (false, criterion.as_str()),
+            };
+
+            // The config is read once, without CanonicalizeHostname, so that one
+            // pass is the final one and never a canonical one
+            let matched = match criterion {
+                "all" => true,
+                "final" => true,
+                "canonical" => false,
+                _ => {
+                    let value = match args.next() {
+                        Some(value) => value,
+                        None => return false,
+                    };
+                    let patterns: Vec<&str> = value.split(',').collect();
+                    match criterion {
+                        "host" => {
+                            let hostname = self.first("hostname").unwrap_or(self.alias);
+                            pattern_list_matches(&patterns, hostname)
+                        }
+                        "originalhost" => pattern_list_matches(&patterns, self.alias),
+                        "user" => pattern_list_matches(
+                            &patterns,
+                            self.first("user").unwrap_or(&self.local_user),
+                        ),
+                        "localuser" => pattern_list_matches(&patterns, &self.local_user),
+                        _ => false,
+                    }
+                }
+            };
+
+            if matched == negated {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+// Collect every concrete alias named by a Host line, following all Includes
+fn collect_aliases(
+    path: &Path,
+    home: &Path,
+    depth: u32,
+    aliases: &mut Vec<String>,
+) -> Result<(), String> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!(
+            "Too many nested Include directives at {}",
+            path.display()
+        ));
+    }
+
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    for line in content.lines() {
+        let (keyword, args) = match parse_line(line) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        match keyword.as_str() {
+            "host" => {
+                for pattern in args {
+                    let concrete = !pattern.contains(['*', '?', '!']);
+                    if concrete && !aliases.contains(&pattern) {
+                        aliases.push(pattern);
+                    }
+                }
+            }
+            "include" => {
+                for pattern in &args {
+                    for file in expand_include(pattern, home) {
+                        collect_aliases(&file, home, depth + 1, aliases)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Split a config line into its lowercased keyword and arguments.
+// Keyword and value may be separated by whitespace or '=', values may be quoted.
+fn parse_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let keyword_end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let keyword = line[..keyword_end].to_lowercase();
+
+    let rest = line[keyword_end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+
+    for c in rest.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            '#' if !in_quotes && !has_arg => break,
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+
+    Some((keyword, args))
+}
+
+// A Host line matches when any pattern matches and no negated pattern does
+fn host_matches(patterns: &[String], alias: &str) -> bool {
+    let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+    pattern_list_matches(&patterns, alias)
+}
+
+fn pattern_list_matches(patterns: &[&str], value: &str) -> bool {
+    let mut matched = false;
+
+    for pattern in patterns {
+        match pattern.strip_prefix('!') {
+            Some(negated) => {
+                if wildcard_matches(negated, value) {
+                    return false;
+                }
+            }
+            None => matched |= wildcard_matches(pattern, value),
+        }
+    }
+
+    matched
+}
+
+// Case-insensitive glob match supporting '*' and '?'
+fn wildcard_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            v = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Include paths are relative to ~/.ssh and may contain wildcards in the file name
+fn expand_include(pattern: &str, home: &Path) -> Vec<PathBuf> {
+    let path = PathBuf::from(expand_path(pattern, home));
+    let path = if path.is_absolute() {
+        path
+    } else {
+        home.join(".ssh").join(path)
+    };
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !file_name.contains(['*', '?']) {
+        return vec![path];
+    }
+
+    let directory = match path.parent() {
+        Some(directory) => directory,
+        None => return Vec::new(),
+    };
+    let mut files: Vec<PathBuf> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| wildcard_matches(&file_name, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    // Glob results are processed in lexical order
+    files.sort();
+    files
+}
+
+fn expand_path(path: &str, home: &Path) -> String {
+    if path == "~" {
+        home.to_string_lossy().to_string()
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        home.join(rest).to_string_lossy().to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+struct Tokens<'a> {
+    hostname: &'a str,
+    alias: &'a str,
+    port: u16,
+    remote_user: &'a str,
+    local_user: &'a str,
+    home: &'a Path,
+}
+
+// Expand the percent tokens of ssh_config(5) that make sense without a live connection
+fn expand_tokens(value: &str, tokens: &Tokens) -> String {
+    let mut expanded = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('h') => expanded.push_str(tokens.hostname),
+            Some('n') => expanded.push_str(tokens.alias),
+            Some('p') => expanded.push_str(&tokens.port.to_string()),
+            Some('r') => expanded.push_str(tokens.remote_user),
+            Some('u') => expanded.push_str(tokens.local_user),
+            Some('d') => expanded.push_str(&tokens.home.to_string_lossy()),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+
+    expanded
+}
+
+fn local_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    // A throwaway home directory with ~/.ssh/config and the files it includes
+    struct TestHome(PathBuf);
+
+    impl TestHome {
+        fn new(config: &str) -> Self {
+            let home =
+                TestHome(std::env::temp_dir().join(format!("ssh-config-{}", Uuid::new_v4())));
+            home.write("config", config);
+            home
+        }
+
+        // Write a file below ~/.ssh
+        fn write(&self, name: &str, content: &str) {
+            let path = self.0.join(".ssh").join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        fn config_path(&self) -> PathBuf {
+            self.0.join(".ssh").join("config")
+        }
+
+        fn resolve(&self, alias: &str) -> ConnectionConfig {
+            resolve_host(&self.config_path(), &self.0, alias).unwrap()
+        }
+    }
+
+    impl Drop for TestHome {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn first_value_wins() {
+        let home = TestHome::new(
+            "ConnectTimeout 7\n\
+             Host web\n  Port 2222\n  User alice\n\
+             Host web\n  Port 3333\n\
+             Host *\n  User bob\n  Port 4444\n  ConnectTimeout 9\n",
+        );
+
+        let config = home.resolve("web");
+        assert_eq!(config.port, 2222);
+        assert_eq!(config.username, "alice");
+        // Directives before the first Host apply to every host
+        assert_eq!(config.connect_timeout_secs, Some(7));
+
+        let other = home.resolve("other");
+        assert_eq!((other.port, other.username.as_str()), (4444, "bob"));
+    }
+
+    #[test]
+    fn includes_files_relative_to_ssh_directory() {
+        let home = TestHome::new("Include conf.d/*\nHost web\n  Port 2300\n");
+        home.write("conf.d/10-web", "Host web\n  HostName web.internal\n");
+        home.write(
+            "conf.d/20-web",
+            "Host web db\n  HostName other\n  Port 2200\n",
+        );
+
+        // Globbed files are read in lexical order, before what follows the Include
+        let config = home.resolve("web");
+        assert_eq!(config.host, "web.internal");
+        assert_eq!(config.port, 2200);
+
+        let mut aliases = Vec::new();
+        collect_aliases(&home.config_path(), &home.0, 0, &mut aliases).unwrap();
+        assert_eq!(aliases, ["web", "db"]);
+    }
+
+    #[test]
+    fn host_patterns_and_negation() {
+        let home = TestHome::new(
+            "Host *.example.com !bastion.example.com\n  User deploy\n\
+             Host db?\n  Port 5432\n",
+        );
+
+        assert_eq!(home.resolve("app.EXAMPLE.com").username, "deploy");
+        assert_eq!(home.resolve("bastion.example.com").username, local_user());
+        assert_eq!(home.resolve("db1").port, 5432);
+        assert_eq!(home.resolve("db12").port, 22);
+
+        let mut aliases = Vec::new();
+        collect_aliases(&home.config_path(), &home.0, 0, &mut aliases).unwrap();
+        assert!(aliases.is_empty());
+    }
+
+    #[test]
+    fn match_criteria() {
+        let home = TestHome::new(
+            "Host web\n  HostName web.internal\n  User alice\n\
+             Match host *.internal user alice\n  Port 2200\n\
+             Match !host *.internal\n  Port 2300\n\
+             Match originalhost other !user alice\n  IdentityFile /keys/other\n\
+             Match exec true\n  User nobody\n",
+        );
+
+        let web = home.resolve("web");
+        assert_eq!(web.port, 2200);
+        assert_eq!(web.private_key_path, None);
+
+        let other = home.resolve("other");
+        assert_eq!(other.port, 2300);
+        // Criteria that can't be evaluated locally never match
+        assert_eq!(other.username, local_user());
+        if local_user() != "alice" {
+            assert_eq!(other.private_key_path.as_deref(), Some("/keys/other"));
+        }
+    }
+
+    #[test]
+    fn expands_tokens_and_home() {
+        let home = TestHome::new(
+            "Host web\n  HostName %h.example.com\n  User alice\n\
+             Port 2022\nIdentityFile ~/.ssh/id_%h_%r_%u_%p\n",
+        );
+
+        let config = home.resolve("web");
+        assert_eq!(config.host, "web.example.com");
+        let key = format!("id_web.example.com_alice_{}_2022", local_user());
+        assert_eq!(
+            config.private_key_path,
+            Some(home.0.join(".ssh").join(key).to_string_lossy().to_string())
+        );
+    }
+
+    #[test]
+    fn resolves_proxy_jump_lists() {
+        let home = TestHome::new(
+            "Host bastion\n  HostName bastion.example.com\n  User jump\n\
+             Host web\n  ProxyJump bastion,ops@10.0.0.5:2222,ssh://[fe80::1]:2022\n\
+             Host loop\n  ProxyJump loop\n\
+             Host broken\n  ProxyJump bastion,\n",
+        );
+
+        let hops = home.resolve("web").jump_hosts.unwrap();
+        let hops: Vec<(&str, u16, &str)> = hops
+            .iter()
+            .map(|hop| (hop.host.as_str(), hop.port, hop.username.as_str()))
+            .collect();
+        let local = local_user();
+        assert_eq!(
+            hops,
+            [
+                ("bastion.example.com", 22, "jump"),
+                ("10.0.0.5", 2222, "ops"),
+                ("fe80::1", 2022, local.as_str()),
+            ]
+        );
+
+        assert!(resolve_host(&home.config_path(), &home.0, "loop").is_err());
+        assert!(resolve_host(&home.config_path(), &home.0, "broken").is_err());
+    }
+}
@@ -1,27 +1,216 @@
+use crate::sftp::error::{is_transport_error, SftpError};
+use crate::sftp::reconnect::{current_pool, with_reconnect};
+use crate::sftp::utils::{run_blocking, run_remote_command, shell_quote};
 use crate::types::*;
-use std::sync::Mutex;
-use tauri::State;
-
-type ConnectionManagerState = Mutex<ConnectionManager>;
+use ssh2::{Session, Sftp};
+use std::path::Path;
+use tauri::AppHandle;
 
+// Space and inode usage of the filesystem holding `path`
 #[tauri::command]
 pub async fn fetch_storage_info(
     connection_id: String,
-    connections: State<'_, ConnectionManagerState>,
-) -> Result<StorageInfo, String> {
-    let conn_manager = connections.lock().unwrap();
-    let session = conn_manager
-        .get(&connection_id)
-        .ok_or("Connection not found")?
-        .clone();
-
-    session
-        .sftp()
-        .map_err(|e| format!("Failed to create SFTP channel: {}", e))?;
-
-    // Logic to fetch storage info
+    path: String,
+    app: AppHandle,
+) -> Result<StorageInfo, SftpError> {
+    let statvfs_path = path.clone();
+    let info = with_reconnect(&app, &connection_id, true, move |sftp| {
+        statvfs_storage_info(sftp, &statvfs_path)
+    })
+    .await?;
+
+    if let Some(info) = info {
+        return Ok(info);
+    }
+
+    // The server doesn't support statvfs@openssh.com, ask df instead
+    let (pool, _) = current_pool(&app, &connection_id)?;
+    let info = run_blocking(move || df_storage_info(pool.session(), &path)).await??;
+    Ok(info)
+}
+
+// Storage info through the statvfs@openssh.com extension, or None when the
+// server can't answer it. The mount point is the topmost ancestor of the path
+// that is still on the same filesystem.
+fn statvfs_storage_info(sftp: &Sftp, path: &str) -> Result<Option<StorageInfo>, SftpError> {
+    let path = sftp
+        .realpath(Path::new(path))
+        .map_err(|e| SftpError::ssh("Failed to resolve path", e).with_path(path))?;
+
+    let stat = match statvfs(sftp, &path) {
+        Ok(stat) => stat,
+        Err(e) if is_transport_error(&e) => {
+            return Err(
+                SftpError::ssh("Failed to query storage info", e).with_path(path.to_string_lossy())
+            )
+        }
+        Err(_) => return Ok(None),
+    };
+
+    let mut mount_point = None;
+    if stat.fsid != 0 {
+        let mut top = path.as_path();
+        for ancestor in path.ancestors().skip(1) {
+            match statvfs(sftp, ancestor) {
+                Ok(parent) if parent.fsid == stat.fsid => top = ancestor,
+                Err(e) if is_transport_error(&e) => {
+                    return Err(SftpError::ssh("Failed to query storage info", e))
+                }
+                _ => break,
+            }
+        }
+        mount_point = Some(top.to_string_lossy().to_string());
+    }
+
+    Ok(Some(StorageInfo {
+        total_space: stat.blocks * stat.block_size,
+        used_space: stat.blocks.saturating_sub(stat.free_blocks) * stat.block_size,
+        free_space: stat.available_blocks * stat.block_size,
+        total_inodes: Some(stat.inodes),
+        free_inodes: Some(stat.free_inodes),
+        mount_point,
+    }))
+}
+
+struct FilesystemStat {
+    block_size: u64,
+    blocks: u64,
+    free_blocks: u64,
+    available_blocks: u64, // Free blocks usable by unprivileged users
+    inodes: u64,
+    free_inodes: u64,
+    fsid: u64,
+}
+
+// ssh2 only exposes the handle variant of the extension, so the path is opened
+// first; OpenSSH lets directories be opened read-only like files
+fn statvfs(sftp: &Sftp, path: &Path) -> Result<FilesystemStat, ssh2::Error> {
+    let stat = sftp.open(path)?.statvfs()?;
+
+    Ok(FilesystemStat {
+        block_size: if stat.f_frsize > 0 {
+            stat.f_frsize
+        } else {
+            stat.f_bsize
+        },
+        blocks: stat.f_blocks,
+        free_blocks: stat.f_bfree,
+        available_blocks: stat.f_bavail,
+        inodes: stat.f_files,
+        free_inodes: stat.f_ffree,
+        fsid: stat.f_fsid,
+    })
+}
+
+// Storage info from `df -Pk`, with inode counts from `df -Pi` where df supports it
+fn df_storage_info(session: &Session, path: &str) -> Result<StorageInfo, SftpError> {
+    let output = run_remote_command(session, &format!("df -Pk -- {}", shell_quote(path)))
+        .map_err(|e| e.with_path(path))?;
+    let (columns, mount_point) = parse_df_output(&output).ok_or_else(|| {
+        SftpError::other(format!("Unexpected df output: {}", output.trim())).with_path(path)
+    })?;
+    let (total, used, available) = (columns[0], columns[1], columns[2]);
+
+    let inodes = run_remote_command(session, &format!("df -Pi -- {}", shell_quote(path)))
+        .ok()
+        .and_then(|output| parse_df_output(&output))
+        .map(|(columns, _)| columns);
+
     Ok(StorageInfo {
-        total_space: 1000000, // Example value
-        used_space: 500000,   // Example value
+        total_space: total * 1024,
+        used_space: used * 1024,
+        free_space: available * 1024,
+        total_inodes: inodes.map(|columns| columns[0]),
+        free_inodes: inodes.map(|columns| columns[2]),
+        mount_point: Some(mount_point),
     })
 }
+
+// The three numeric columns and the mount point of POSIX df output. The
+// filesystem name and mount point may contain spaces, so the row is split
+// around the capacity column, the only one ending in '%'.
+fn parse_df_output(output: &str) -> Option<([u64; 3], String)> {
+    let row = output.lines().nth(1)?;
+    let fields: Vec<&str> = row.split_whitespace().collect();
+    let capacity = fields.iter().rposition(|field| field.ends_with('%'))?;
+    if capacity < 4 || capacity + 1 >= fields.len() {
+        return None;
+    }
+
+    let mut columns = [0u64; 3];
+    for (column, field) in columns.iter_mut().zip(&fields[capacity - 3..capacity]) {
+        *column = field.parse().ok()?;
+    }
+
+    Some((columns, fields[capacity + 1..].join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_gnu_df() {
+        let output = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n\
+                      /dev/sda1        102350592 52430848  44658432      55% /\n";
+        assert_eq!(
+            parse_df_output(output),
+            Some(([102350592, 52430848, 44658432], "/".to_string()))
+        );
+
+        let inodes = "Filesystem      Inodes  IUsed   IFree IUse% Mounted on\n\
+                      /dev/sda1      6553600 412345 6141255    7% /\n";
+        assert_eq!(
+            parse_df_output(inodes),
+            Some(([6553600, 412345, 6141255], "/".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_busybox_df() {
+        let output = "Filesystem           1024-blocks    Used Available Capacity Mounted on\n\
+                      overlay                 61255652 9871236  48242640  17% /\n";
+        assert_eq!(
+            parse_df_output(output),
+            Some(([61255652, 9871236, 48242640], "/".to_string()))
+        );
+    }
+
+    #[test]
+    fn keeps_spaces_in_names() {
+        let output = "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+                      //nas/My Share 1000 250 750 25% /mnt/My Share\n";
+        assert_eq!(
+            parse_df_output(output),
+            Some(([1000, 250, 750], "/mnt/My Share".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        // Filesystems without inodes, e.g. vfat, report them as '-'
+        let inodes = "Filesystem     Inodes IUsed IFree IUse% Mounted on\n\
+                      /dev/sdb1           -     -     -     - /boot/efi\n";
+        assert_eq!(parse_df_output(inodes), None);
+
+        let partial = "Filesystem Inodes IUsed IFree IUse% Mounted on\n\
+                       /dev/sdb1 0 - 0 0% /media\n";
+        assert_eq!(parse_df_output(partial), None);
+    }
+
+    #[test]
+    fn rejects_output_without_row() {
+        assert_eq!(parse_df_output(""), None);
+        assert_eq!(
+            parse_df_output("Filesystem 1024-blocks Used Available Capacity Mounted on\n"),
+            None
+        );
+        // No mount point after the capacity column
+        assert_eq!(
+            parse_df_output(
+                "Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/sda1 10 5 5 50%\n"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use crate::sftp::engine::SftpEngine;
+use crate::sftp::error::{ErrorKind, SftpError};
+use crate::sftp::file_operations::{start_download, start_upload, TRANSFER_CANCEL_MAP};
+use crate::sftp::queue::TransferQueue;
+use crate::types::*;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, Window};
+use uuid::Uuid;
+
+// Bytes before the resume point compared on both sides when a resume is verified
+const VERIFY_TAIL: u64 = 64 * 1024;
+
+// Serializes reads and writes of the records file
+static RECORDS_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+impl TransferRecord {
+    // Record of a new transfer over a connection, with a fresh transfer id
+    pub fn new(
+        app: &AppHandle,
+        connection_id: String,
+        direction: TransferDirection,
+        local_path: String,
+        remote_path: String,
+    ) -> Result<Self, SftpError> {
+        let config = app
+            .state::<ConnectionManager>()
+            .config(&connection_id)
+            .ok_or_else(SftpError::connection_not_found)?;
+
+        Ok(TransferRecord {
+            transfer_id: Uuid::new_v4().to_string(),
+            connection_id,
+            direction,
+            local_path,
+            remote_path,
+            host: config.host,
+            port: config.port,
+            username: config.username,
+        })
+    }
+}
+
+// Resume a paused transfer, or an upload or download that failed, continuing
+// after what its partial target already holds. `connection_id` is the connection
+// to resume a failed one on when the recorded one is gone, e.g. after a restart.
+// The transfer keeps its id either way.
+#[tauri::command]
+pub async fn resume_transfer(
+    transfer_id: String,
+    connection_id: Option<String>,
+    verify: Option<bool>,
+    window: Window,
+) -> Result<String, SftpError> {
+    let app = window.app_handle().clone();
+    let running = TRANSFER_CANCEL_MAP
+        .lock()
+        .unwrap()
+        .get(&transfer_id)
+        .cloned();
+    if let Some(control) = running {
+        if !control.pause_flag.swap(false, Ordering::Relaxed) {
+            return Err(SftpError::new(
+                ErrorKind::InvalidInput,
+                format!("Transfer with ID {} is not paused", transfer_id),
+            ));
+        }
+        // A transfer paused while queued may start now
+        app.state::<TransferQueue>().schedule(&app);
+        emit_resumed(&window, &transfer_id);
+        return Ok(transfer_id);
+    }
+
+    let mut record = load_records(&app)?.remove(&transfer_id).ok_or_else(|| {
+        SftpError::new(
+            ErrorKind::NotFound,
+            format!("No resumable transfer with ID {}", transfer_id),
+        )
+    })?;
+    if let Some(connection_id) = connection_id {
+        let rebound = TransferRecord::new(
+            &app,
+            connection_id,
+            record.direction,
+            record.local_path.clone(),
+            record.remote_path.clone(),
+        )?;
+        // Another server's file would be appended to the partial target
+        if (&rebound.host, rebound.port, &rebound.username)
+            != (&record.host, record.port, &record.username)
+        {
+            return Err(SftpError::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Transfer with ID {} was made as {}@{}:{}, not over this connection",
+                    transfer_id, record.username, record.host, record.port
+                ),
+            ));
+        }
+        record = TransferRecord {
+            transfer_id: record.transfer_id,
+            ..rebound
+        };
+    }
+
+    let verify = verify.unwrap_or(false);
+    let transfer_id = match record.direction {
+        TransferDirection::Upload => start_upload(record, true, verify, window.clone()).await?,
+        TransferDirection::Download => start_download(record, true, verify, window.clone()).await?,
+    };
+    emit_resumed(&window, &transfer_id);
+    Ok(transfer_id)
+}
+
+fn emit_resumed(window: &Window, transfer_id: &str) {
+    window
+        .emit(
+            "transfer_resumed",
+            serde_json::json!({ "transfer_id": transfer_id }),
+        )
+        .ok();
+}
+
+// Transfers that failed and can be resumed, including those of earlier runs
+#[tauri::command]
+pub async fn get_resumable_transfers(app: AppHandle) -> Result<Vec<TransferRecord>, SftpError> {
+    let records = load_records(&app)?;
+    let running = TRANSFER_CANCEL_MAP.lock().unwrap();
+    Ok(records
+        .into_values()
+        .filter(|record| !running.contains_key(&record.transfer_id))
+        .collect())
+}
+
+// Forget a failed transfer; its partial target is left as it is
+#[tauri::command]
+pub async fn discard_transfer(transfer_id: String, app: AppHandle) -> Result<(), SftpError> {
+    remove_record(&app, &transfer_id)
+}
+
+// Where a resumed transfer continues: the size of its partial target, unless
+// that is larger than the source or, with `verify`, its last bytes differ from
+// the source's. Then the transfer starts over.
+pub async fn resume_offset(
+    engine: &SftpEngine,
+    record: &TransferRecord,
+    verify: bool,
+) -> Result<u64, SftpError> {
+    let local_size = match std::fs::metadata(&record.local_path) {
+        Ok(metadata) => Some(metadata.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(
+                SftpError::local("Failed to stat local file", e).with_path(&record.local_path)
+            )
+        }
+    };
+    let remote_size = match engine.stat(&record.remote_path).await {
+        Ok(stat) => Some(stat.size.unwrap_or(0)),
+        Err(e) if e.kind == ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    let (partial, source) = match record.direction {
+        TransferDirection::Upload => (remote_size, local_size),
+        TransferDirection::Download => (local_size, remote_size),
+    };
+    let partial = match (partial, source) {
+        (Some(partial), Some(source)) if partial <= source => partial,
+        // Nothing to resume, or a target that can't be a prefix of the source
+        _ => return Ok(0),
+    };
+    if !verify || partial == 0 {
+        return Ok(partial);
+    }
+
+    let len = partial.min(VERIFY_TAIL);
+    let remote = engine
+        .read_at(&record.remote_path, partial - len, len as usize)
+        .await?;
+    let mut local = Vec::with_capacity(len as usize);
+    std::fs::File::open(&record.local_path)
+        .and_then(|mut file| {
+            file.seek(SeekFrom::Start(partial - len))?;
+            file.take(len).read_to_end(&mut local)
+        })
+        .map_err(|e| {
+            SftpError::local("Failed to read local file", e).with_path(&record.local_path)
+        })?;
+
+    Ok(if remote == local { partial } else { 0 })
+}
+
+// Persist a transfer that is starting
+pub fn save_record(app: &AppHandle, record: &TransferRecord) -> Result<(), SftpError> {
+    let _lock = RECORDS_LOCK.lock().unwrap();
+    let mut records = read_records(app)?;
+    records.insert(record.transfer_id.clone(), record.clone());
+    write_records(app, &records)
+}
+
+// Forget a transfer that finished or was cancelled
+pub fn remove_record(app: &AppHandle, transfer_id: &str) -> Result<(), SftpError> {
+    let _lock = RECORDS_LOCK.lock().unwrap();
+    let mut records = read_records(app)?;
+    if records.remove(transfer_id).is_some() {
+        write_records(app, &records)?;
+    }
+    Ok(())
+}
+
+fn load_records(app: &AppHandle) -> Result<HashMap<String, TransferRecord>, SftpError> {
+    let _lock = RECORDS_LOCK.lock().unwrap();
+    read_records(app)
+}
+
+fn records_path(app: &AppHandle) -> Result<PathBuf, SftpError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join("transfers.json"))
+        .map_err(|e| SftpError::other(format!("Failed to find app data directory: {}", e)))
+}
+
+fn read_records(app: &AppHandle) -> Result<HashMap<String, TransferRecord>, SftpError> {
+    let path = records_path(app)?;
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => {
+            return Err(SftpError::local("Failed to read transfer records", e)
+                .with_path(path.to_string_lossy()))
+        }
+    };
+    serde_json::from_str(&contents).map_err(|e| {
+        SftpError::other(format!("Failed to parse transfer records: {}", e))
+            .with_path(path.to_string_lossy())
+    })
+}
+
+fn write_records(
+    app: &AppHandle,
+    records: &HashMap<String, TransferRecord>,
+) -> Result<(), SftpError> {
+    let path = records_path(app)?;
+    let contents = serde_json::to_string_pretty(records)
+        .map_err(|e| SftpError::other(format!("Failed to serialize transfer records: {}", e)))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            SftpError::local("Failed to create app data directory", e)
+                .with_path(parent.to_string_lossy())
+        })?;
+    }
+    std::fs::write(&path, contents).map_err(|e| {
+        SftpError::local("Failed to write transfer records", e).with_path(path.to_string_lossy())
+    })
+}
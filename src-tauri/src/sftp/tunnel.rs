@@ -0,0 +1,208 @@
+use ssh2::{BlockDirections, Channel, Session};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, Interest};
+
+// Open a direct-tcpip channel from `session` to host:port and expose it as a
+// loopback socket, so the next ssh2::Session can use it as its TCP stream.
+// The session is moved to a pump thread that lives as long as the tunnel;
+// `socket` is a handle to the socket the session talks over.
+pub fn open_tunnel(
+    session: Session,
+    socket: TcpStream,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream, String> {
+    let channel = session
+        .channel_direct_tcpip(host, port, None)
+        .map_err(|e| format!("Failed to open tunnel to {}:{}: {}", host, port, e))?;
+
+    expose_channel(session, socket, channel, false)
+}
+
+// Start `subsystem` (e.g. "sftp") on a new channel and expose its stdin/stdout
+// as a loopback socket, for clients that speak the protocol themselves. The
+// session stays usable for other work; `socket` is the socket it talks over.
+pub fn open_subsystem(
+    session: Session,
+    socket: TcpStream,
+    subsystem: &str,
+) -> Result<TcpStream, String> {
+    let mut channel = session
+        .channel_session()
+        .map_err(|e| format!("Failed to open channel: {}", e))?;
+    channel
+        .subsystem(subsystem)
+        .map_err(|e| format!("Failed to start {} subsystem: {}", subsystem, e))?;
+
+    expose_channel(session, socket, channel, true)
+}
+
+fn expose_channel(
+    session: Session,
+    socket: TcpStream,
+    channel: Channel,
+    shared: bool,
+) -> Result<TcpStream, String> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .map_err(|e| format!("Failed to bind tunnel socket: {}", e))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to bind tunnel socket: {}", e))?;
+
+    let client = TcpStream::connect(address)
+        .map_err(|e| format!("Failed to connect tunnel socket: {}", e))?;
+    let client_address = client
+        .local_addr()
+        .map_err(|e| format!("Failed to connect tunnel socket: {}", e))?;
+
+    // Only accept the socket we just connected, not another local process
+    let local = loop {
+        let (stream, peer) = listener
+            .accept()
+            .map_err(|e| format!("Failed to accept tunnel socket: {}", e))?;
+        if peer == client_address {
+            break stream;
+        }
+    };
+
+    thread::spawn(move || pump(session, channel, local, socket, shared));
+
+    Ok(client)
+}
+
+// Copy bytes between the channel and the local socket until either side closes.
+// When nothing can move, the pump waits until the local socket or the
+// session's socket is ready. A session of its own runs non-blocking and is
+// disconnected with the tunnel. A shared session stays blocking for its other
+// users, so the pump only reads what is already queued for the channel; an
+// empty write pulls the session's incoming packets in without waiting.
+fn pump(session: Session, mut channel: Channel, local: TcpStream, socket: TcpStream, shared: bool) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build();
+    if let Ok(runtime) = runtime {
+        session.set_blocking(shared);
+        runtime
+            .block_on(pump_until_closed(
+                &session,
+                &mut channel,
+                local,
+                socket,
+                shared,
+            ))
+            .ok();
+    }
+
+    session.set_blocking(true);
+    channel.close().ok();
+    if !shared {
+        session.disconnect(None, "Tunnel closed", None).ok();
+    }
+}
+
+async fn pump_until_closed(
+    session: &Session,
+    channel: &mut Channel,
+    local: TcpStream,
+    socket: TcpStream,
+    shared: bool,
+) -> io::Result<()> {
+    local.set_nonblocking(true)?;
+    socket.set_nonblocking(true)?;
+    let mut local = tokio::net::TcpStream::from_std(local)?;
+    let socket = tokio::net::TcpStream::from_std(socket)?;
+    let mut buffer = [0u8; 32 * 1024];
+
+    loop {
+        let mut idle = true;
+
+        match local.try_read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                write_channel(session, &socket, channel, &buffer[..n]).await?;
+                idle = false;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+
+        let readable = !shared || channel.eof() || channel.read_window().available > 0;
+        if readable {
+            match channel.read(&mut buffer) {
+                Ok(0) if channel.eof() => return Ok(()),
+                Ok(0) => {}
+                Ok(n) => {
+                    local.write_all(&buffer[..n]).await?;
+                    idle = false;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if idle {
+            // A shared session's directions are set by its other users too,
+            // and it is kept alive by the connection's keepalive task
+            let (interest, next_keepalive) = match shared {
+                true => (Interest::READABLE, 0),
+                false => (
+                    blocked_on(session),
+                    session.keepalive_send().unwrap_or(1).max(1),
+                ),
+            };
+            tokio::select! {
+                ready = local.readable() => ready?,
+                ready = socket_ready(&socket, interest) => {
+                    ready?;
+                    if shared {
+                        channel.write(&[]).map(|_| ())?;
+                    }
+                }
+                _ = tokio::time::sleep(Duration::from_secs(next_keepalive as u64)), if !shared => {}
+            }
+        }
+    }
+}
+
+async fn write_channel(
+    session: &Session,
+    socket: &tokio::net::TcpStream,
+    channel: &mut Channel,
+    mut data: &[u8],
+) -> io::Result<()> {
+    while !data.is_empty() {
+        match channel.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                socket_ready(socket, blocked_on(session)).await?
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    // No flush: Channel::flush discards unread incoming data in libssh2
+    Ok(())
+}
+
+// The directions a non-blocking session last waited on, as socket readiness
+fn blocked_on(session: &Session) -> Interest {
+    match session.block_directions() {
+        BlockDirections::Outbound => Interest::WRITABLE,
+        BlockDirections::Both => Interest::READABLE | Interest::WRITABLE,
+        BlockDirections::Inbound | BlockDirections::None => Interest::READABLE,
+    }
+}
+
+// Wait until the session's socket is ready. libssh2 does its I/O through its
+// own handle, so tokio never sees the socket drained; readiness is cleared
+// here, before libssh2 reads, so data arriving after that wakes the next wait.
+async fn socket_ready(socket: &tokio::net::TcpStream, interest: Interest) -> io::Result<()> {
+    socket.ready(interest).await?;
+    socket
+        .try_io(interest, || Err::<(), _>(ErrorKind::WouldBlock.into()))
+        .ok();
+    Ok(())
+}
@@ -1,4 +1,10 @@
+use crate::sftp::error::SftpError;
+use crate::types::EntryType;
+use ssh2::{FileStat, FileType, Session};
+use std::io::{self, Read};
+use std::net::{TcpStream, ToSocketAddrs};
 use std::path::Path;
+use std::time::Duration;
 
 pub fn get_parent_path(path: &str) -> String {
     Path::new(path)
@@ -8,30 +14,428 @@ pub fn get_parent_path(path: &str) -> String {
         .replace("\\", "/")
 }
 
+// Dotfiles are hidden by convention; "." and ".." are not entries of their own
+pub fn is_hidden(name: &str) -> bool {
+    name.starts_with('.') && name != "." && name != ".."
+}
+
+pub fn is_hidden_path(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .map(|name| is_hidden(&name.to_string_lossy()))
+        .unwrap_or(false)
+}
+
+pub fn entry_type(stat: &FileStat) -> EntryType {
+    match stat.file_type() {
+        FileType::RegularFile => EntryType::File,
+        FileType::Directory => EntryType::Directory,
+        FileType::Symlink => EntryType::Symlink,
+        _ => EntryType::Other,
+    }
+}
+
+// File type bits of st_mode
+const S_IFMT: u32 = 0o170000;
+const S_IFSOCK: u32 = 0o140000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFBLK: u32 = 0o060000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFIFO: u32 = 0o010000;
+
+// Special bits
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
+const S_ISVTX: u32 = 0o1000;
+
+// Bits each class of a symbolic mode can change, special bits included
+const USER_BITS: u32 = S_ISUID | 0o700;
+const GROUP_BITS: u32 = S_ISGID | 0o070;
+const OTHER_BITS: u32 = S_ISVTX | 0o007;
+
+// Mode in `ls -l` style, e.g. "drwxr-sr-x" or "lrwxrwxrwx"
 pub fn format_permissions(mode: u32) -> String {
     let mut perms = String::new();
 
     // File type
-    if mode & 0o40000 != 0 {
-        perms.push('d');
-    } else {
-        perms.push('-');
+    perms.push(match mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFIFO => 'p',
+        S_IFSOCK => 's',
+        S_IFCHR => 'c',
+        S_IFBLK => 'b',
+        _ => '-',
+    });
+
+    // Owner, group and other permissions; the special bit of each class shows
+    // in its execute position, lowercase when execute is set as well
+    let classes = [(6, S_ISUID, 's'), (3, S_ISGID, 's'), (0, S_ISVTX, 't')];
+    for (shift, special, special_char) in classes {
+        let bits = (mode >> shift) & 0o7;
+        perms.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        perms.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        perms.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
     }
 
-    // Owner permissions
-    perms.push(if mode & 0o400 != 0 { 'r' } else { '-' });
-    perms.push(if mode & 0o200 != 0 { 'w' } else { '-' });
-    perms.push(if mode & 0o100 != 0 { 'x' } else { '-' });
+    perms
+}
+
+// A chmod mode: octal ("755", "4755") or comma separated symbolic clauses
+// ("u+x,g-w,o=r"). Symbolic modes depend on the current mode, so a parsed mode
+// is applied to each file separately.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModeSpec {
+    Octal(u32),
+    Symbolic(Vec<ModeClause>),
+}
+
+// One symbolic clause: the classes it affects and its operations in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeClause {
+    who: u32, // Union of USER_BITS, GROUP_BITS and OTHER_BITS
+    actions: Vec<(char, ModePerms)>,
+}
 
-    // Group permissions
-    perms.push(if mode & 0o040 != 0 { 'r' } else { '-' });
-    perms.push(if mode & 0o020 != 0 { 'w' } else { '-' });
-    perms.push(if mode & 0o010 != 0 { 'x' } else { '-' });
+#[derive(Debug, Clone, PartialEq)]
+enum ModePerms {
+    Letters(String), // Any of rwxXst
+    CopyOf(char),    // The current bits of class u, g or o
+}
 
-    // Other permissions
-    perms.push(if mode & 0o004 != 0 { 'r' } else { '-' });
-    perms.push(if mode & 0o002 != 0 { 'w' } else { '-' });
-    perms.push(if mode & 0o001 != 0 { 'x' } else { '-' });
+impl ModeSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err("Mode is empty".to_string());
+        }
 
-    perms
+        if spec.chars().all(|c| c.is_ascii_digit()) {
+            return match u32::from_str_radix(spec, 8) {
+                Ok(mode) if mode <= 0o7777 => Ok(ModeSpec::Octal(mode)),
+                _ => Err(format!("Invalid octal mode: {}", spec)),
+            };
+        }
+
+        spec.split(',')
+            .map(|clause| {
+                parse_clause(clause).ok_or(format!("Invalid mode clause '{}' in {}", clause, spec))
+            })
+            .collect::<Result<_, _>>()
+            .map(ModeSpec::Symbolic)
+    }
+
+    // Permission and special bits (0o7777) after applying the mode to a file's
+    // st_mode. Like chmod, a symbolic mode without classes affects all of them
+    // (no umask is applied), X only grants execute to directories and files that
+    // are already executable by someone, and = keeps the setuid and setgid bits
+    // of a directory.
+    pub fn apply(&self, mode: u32) -> u32 {
+        let clauses = match self {
+            ModeSpec::Octal(bits) => return *bits,
+            ModeSpec::Symbolic(clauses) => clauses,
+        };
+
+        let is_directory = mode & S_IFMT == S_IFDIR;
+        let mut bits = mode & 0o7777;
+        for clause in clauses {
+            for (op, perms) in &clause.actions {
+                let change = match perms {
+                    ModePerms::Letters(letters) => letter_bits(letters, bits, is_directory),
+                    ModePerms::CopyOf(class) => {
+                        let shift = match class {
+                            'u' => 6,
+                            'g' => 3,
+                            _ => 0,
+                        };
+                        ((bits >> shift) & 0o7) * 0o111
+                    }
+                } & clause.who;
+
+                bits = match op {
+                    '+' => bits | change,
+                    '-' => bits & !change,
+                    _ => {
+                        let kept = if is_directory { S_ISUID | S_ISGID } else { 0 };
+                        (bits & !(clause.who & !kept)) | change
+                    }
+                };
+            }
+        }
+        bits
+    }
+}
+
+// [ugoa]*([-+=]([rwxXst]*|[ugo]))+
+fn parse_clause(clause: &str) -> Option<ModeClause> {
+    let mut chars = clause.chars().peekable();
+
+    let mut who = 0;
+    while let Some(&c) = chars.peek() {
+        who |= match c {
+            'u' => USER_BITS,
+            'g' => GROUP_BITS,
+            'o' => OTHER_BITS,
+            'a' => USER_BITS | GROUP_BITS | OTHER_BITS,
+            _ => break,
+        };
+        chars.next();
+    }
+    if who == 0 {
+        who = USER_BITS | GROUP_BITS | OTHER_BITS;
+    }
+
+    let mut actions = Vec::new();
+    while let Some(op) = chars.next() {
+        if !matches!(op, '+' | '-' | '=') {
+            return None;
+        }
+
+        let perms = match chars.peek() {
+            Some(&class) if matches!(class, 'u' | 'g' | 'o') => {
+                chars.next();
+                ModePerms::CopyOf(class)
+            }
+            _ => {
+                let mut letters = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !"rwxXst".contains(c) {
+                        break;
+                    }
+                    letters.push(c);
+                    chars.next();
+                }
+                ModePerms::Letters(letters)
+            }
+        };
+        actions.push((op, perms));
+    }
+
+    if actions.is_empty() {
+        return None;
+    }
+    Some(ModeClause { who, actions })
+}
+
+// Bits named by permission letters, before masking by the clause's classes
+fn letter_bits(letters: &str, bits: u32, is_directory: bool) -> u32 {
+    letters.chars().fold(0, |acc, c| {
+        acc | match c {
+            'r' => 0o444,
+            'w' => 0o222,
+            'x' => 0o111,
+            'X' if is_directory || bits & 0o111 != 0 => 0o111,
+            's' => S_ISUID | S_ISGID,
+            't' => S_ISVTX,
+            _ => 0,
+        }
+    })
+}
+
+// TcpStream::connect with a timeout, trying every address the host resolves to
+pub fn connect_with_timeout(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = None;
+
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} did not resolve to any address", host),
+        )
+    }))
+}
+
+// Run blocking ssh2 work on the blocking thread pool so it doesn't stall the async runtime
+pub async fn run_blocking<T, F>(work: F) -> Result<T, String>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| format!("Blocking task failed: {}", e))
+}
+
+// Run a command on an exec channel and return its stdout, failing on a non-zero exit status
+pub fn run_remote_command(session: &Session, command: &str) -> Result<String, SftpError> {
+    let mut channel = session
+        .channel_session()
+        .map_err(|e| SftpError::ssh("Failed to open channel", e))?;
+    channel
+        .exec(command)
+        .map_err(|e| SftpError::ssh(&format!("Failed to run {}", command), e))?;
+    // Nothing is sent on stdin; a forced sftp-server would otherwise wait for it
+    channel.send_eof().ok();
+
+    let mut output = String::new();
+    channel
+        .read_to_string(&mut output)
+        .map_err(|e| SftpError::io(&format!("Failed to read output of {}", command), e, session))?;
+    let mut errors = String::new();
+    channel.stderr().read_to_string(&mut errors).ok();
+    channel.wait_close().ok();
+
+    match channel.exit_status() {
+        Ok(0) => Ok(output),
+        Ok(status) => Err(SftpError::other(format!(
+            "{} exited with status {}: {}",
+            command,
+            status,
+            errors.trim()
+        ))),
+        Err(e) => Err(SftpError::ssh(&format!("Failed to run {}", command), e)),
+    }
+}
+
+// Quote an argument for a POSIX shell
+pub fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: u32 = 0o100000;
+    const DIR: u32 = S_IFDIR;
+
+    fn apply(spec: &str, mode: u32) -> u32 {
+        ModeSpec::parse(spec).unwrap().apply(mode)
+    }
+
+    #[test]
+    fn formats_file_types() {
+        assert_eq!(format_permissions(FILE | 0o644), "-rw-r--r--");
+        assert_eq!(format_permissions(DIR | 0o755), "drwxr-xr-x");
+        assert_eq!(format_permissions(S_IFLNK | 0o777), "lrwxrwxrwx");
+        assert_eq!(format_permissions(S_IFIFO | 0o600), "prw-------");
+        assert_eq!(format_permissions(S_IFSOCK | 0o755), "srwxr-xr-x");
+        assert_eq!(format_permissions(S_IFCHR | 0o620), "crw--w----");
+        assert_eq!(format_permissions(S_IFBLK | 0o660), "brw-rw----");
+        // Servers that send no type bits
+        assert_eq!(format_permissions(0o640), "-rw-r-----");
+    }
+
+    #[test]
+    fn formats_special_bits() {
+        assert_eq!(format_permissions(FILE | 0o4755), "-rwsr-xr-x");
+        assert_eq!(format_permissions(FILE | 0o4644), "-rwSr--r--");
+        assert_eq!(format_permissions(DIR | 0o2775), "drwxrwsr-x");
+        assert_eq!(format_permissions(FILE | 0o2640), "-rw-r-S---");
+        assert_eq!(format_permissions(DIR | 0o1777), "drwxrwxrwt");
+        assert_eq!(format_permissions(DIR | 0o1770), "drwxrwx--T");
+        assert_eq!(format_permissions(FILE | 0o7000), "---S--S--T");
+        assert_eq!(format_permissions(FILE | 0o7777), "-rwsrwsrwt");
+    }
+
+    #[test]
+    fn parses_octal_modes() {
+        assert_eq!(ModeSpec::parse("755"), Ok(ModeSpec::Octal(0o755)));
+        assert_eq!(ModeSpec::parse("0644"), Ok(ModeSpec::Octal(0o644)));
+        assert_eq!(ModeSpec::parse("4755"), Ok(ModeSpec::Octal(0o4755)));
+        assert_eq!(ModeSpec::parse(" 0 "), Ok(ModeSpec::Octal(0)));
+        assert!(ModeSpec::parse("789").is_err());
+        assert!(ModeSpec::parse("17777").is_err());
+    }
+
+    #[test]
+    fn octal_modes_replace_all_bits() {
+        assert_eq!(apply("640", FILE | 0o4777), 0o640);
+        assert_eq!(apply("1777", DIR | 0o755), 0o1777);
+    }
+
+    #[test]
+    fn rejects_invalid_modes() {
+        for spec in [
+            "", "u", "ug", "x", "u+y", "u+x,", ",u+x", "u+x,,g-w", "u+rg", "z=r", "u!x",
+        ] {
+            assert!(
+                ModeSpec::parse(spec).is_err(),
+                "{:?} should not parse",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn adds_and_removes_bits() {
+        assert_eq!(apply("u+x", FILE | 0o644), 0o744);
+        assert_eq!(apply("g-w", FILE | 0o664), 0o644);
+        assert_eq!(apply("o-rwx", FILE | 0o777), 0o770);
+        assert_eq!(apply("go+r", FILE | 0o600), 0o644);
+        assert_eq!(apply("a+x", FILE | 0o644), 0o755);
+        assert_eq!(apply("+x", FILE | 0o644), 0o755);
+        assert_eq!(apply("-w", FILE | 0o666), 0o444);
+    }
+
+    #[test]
+    fn assigns_bits() {
+        assert_eq!(apply("o=r", FILE | 0o777), 0o774);
+        assert_eq!(apply("u=rw,go=", FILE | 0o755), 0o600);
+        assert_eq!(apply("a=rx", FILE | 0o640), 0o555);
+        assert_eq!(apply("=", FILE | 0o777), 0);
+    }
+
+    #[test]
+    fn applies_clauses_in_order() {
+        assert_eq!(apply("u+x,g-w,o=r", FILE | 0o666), 0o744);
+        assert_eq!(apply("a-x,u+x", FILE | 0o777), 0o766);
+        assert_eq!(apply("u+x-w", FILE | 0o644), 0o544);
+        assert_eq!(apply("u=rwx,g=u-w", FILE), 0o750);
+    }
+
+    #[test]
+    fn copies_bits_from_another_class() {
+        assert_eq!(apply("g=u", FILE | 0o740), 0o770);
+        assert_eq!(apply("o=g", FILE | 0o751), 0o755);
+        assert_eq!(apply("go=u", FILE | 0o600), 0o666);
+        assert_eq!(apply("u+o", FILE | 0o404), 0o404);
+    }
+
+    #[test]
+    fn conditional_execute_bit() {
+        assert_eq!(apply("a+X", DIR | 0o644), 0o755);
+        assert_eq!(apply("a+X", FILE | 0o644), 0o644);
+        assert_eq!(apply("a+X", FILE | 0o744), 0o755);
+        // X sees the bits set by earlier clauses
+        assert_eq!(apply("u+x,go+X", FILE | 0o644), 0o755);
+    }
+
+    #[test]
+    fn special_bits() {
+        assert_eq!(apply("u+s", FILE | 0o755), 0o4755);
+        assert_eq!(apply("g+s", DIR | 0o775), 0o2775);
+        assert_eq!(apply("+s", FILE | 0o755), 0o6755);
+        assert_eq!(apply("+t", DIR | 0o777), 0o1777);
+        assert_eq!(apply("o+t", DIR | 0o777), 0o1777);
+        assert_eq!(apply("u-s", FILE | 0o4755), 0o755);
+        // s means nothing for other, t nothing for user and group
+        assert_eq!(apply("o+s", FILE | 0o755), 0o755);
+        assert_eq!(apply("ug+t", FILE | 0o755), 0o755);
+    }
+
+    #[test]
+    fn assignment_clears_special_bits_of_files_only() {
+        assert_eq!(apply("u=rwx", FILE | 0o4755), 0o755);
+        assert_eq!(apply("g=rx", DIR | 0o2775), 0o2755);
+        assert_eq!(apply("o=rx", DIR | 0o1777), 0o775);
+    }
+
+    #[test]
+    fn keeps_file_type_out_of_the_result() {
+        assert_eq!(apply("u+x", DIR | 0o644), 0o744);
+        assert_eq!(apply("a=", S_IFLNK | 0o777), 0);
+    }
 }
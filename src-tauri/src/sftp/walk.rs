@@ -0,0 +1,332 @@
+use crate::sftp::error::{unless_transport, SftpError};
+use crate::types::SymlinkPolicy;
+use futures_util::FutureExt;
+use ssh2::{FileStat, Sftp};
+use std::future::{ready, Future};
+use std::path::Path;
+
+// What a recursive walk does with one directory entry
+pub enum WalkStep {
+    Directory(WalkDir), // Walk into this directory
+    File(FileStat),     // A regular entry, or the target of a followed link
+    Link(FileStat),     // A link to treat as a link: recreated on copy, counted on size
+    Skip,
+}
+
+impl WalkStep {
+    // What a size walk counts for an entry it doesn't walk into
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            WalkStep::File(stat) | WalkStep::Link(stat) => Some(stat.size.unwrap_or(0)),
+            WalkStep::Directory(_) | WalkStep::Skip => None,
+        }
+    }
+}
+
+// A directory a walk enters. A following walk also carries the canonical paths
+// of the directories it is inside, from the root down to this one, so a link
+// back into that chain is recognized as a cycle.
+pub struct WalkDir {
+    pub path: String,
+    ancestors: Vec<String>,
+}
+
+impl WalkDir {
+    fn child(&self, path: String, walk: &SymlinkWalk) -> WalkDir {
+        let mut ancestors = Vec::new();
+        if walk.policy == SymlinkPolicy::Follow {
+            ancestors = self.ancestors.clone();
+            ancestors.push(path.clone());
+        }
+        WalkDir { path, ancestors }
+    }
+
+    // Whether entering `canonical` would walk a directory this one is already
+    // inside: one of its ancestors, or a directory above one of them
+    fn would_cycle(&self, canonical: &str) -> bool {
+        let prefix = format!("{}/", canonical.trim_end_matches('/'));
+        self.ancestors
+            .iter()
+            .any(|ancestor| ancestor == canonical || ancestor.starts_with(&prefix))
+    }
+}
+
+// Symlink handling of one recursive walk
+pub struct SymlinkWalk {
+    pub policy: SymlinkPolicy,
+}
+
+impl SymlinkWalk {
+    pub fn new(policy: SymlinkPolicy) -> Self {
+        SymlinkWalk { policy }
+    }
+}
+
+// Lookups a walk makes on the backend it runs on. They fail when the path
+// can't be resolved, e.g. a dangling or looping link, and set transport_lost
+// only when the connection broke.
+pub trait WalkLookup {
+    // Attributes of a path itself, without following a final link
+    fn link_stat(&self, path: &str) -> impl Future<Output = Result<FileStat, SftpError>> + Send;
+    // Attributes of what a path resolves to
+    fn target_stat(&self, path: &str) -> impl Future<Output = Result<FileStat, SftpError>> + Send;
+    // The canonical path of a path, with every link resolved
+    fn canonical_path(&self, path: &str) -> impl Future<Output = Result<String, SftpError>> + Send;
+}
+
+// ssh2 lookups block the calling thread, so their futures are ready at once
+impl WalkLookup for Sftp {
+    fn link_stat(&self, path: &str) -> impl Future<Output = Result<FileStat, SftpError>> + Send {
+        ready(
+            self.lstat(Path::new(path))
+                .map_err(|e| SftpError::ssh("Failed to stat", e).with_path(path)),
+        )
+    }
+
+    fn target_stat(&self, path: &str) -> impl Future<Output = Result<FileStat, SftpError>> + Send {
+        ready(
+            self.stat(Path::new(path))
+                .map_err(|e| SftpError::ssh("Failed to resolve link", e).with_path(path)),
+        )
+    }
+
+    fn canonical_path(&self, path: &str) -> impl Future<Output = Result<String, SftpError>> + Send {
+        ready(
+            self.realpath(Path::new(path))
+                .map(|canonical| canonical.to_string_lossy().replace("\\", "/"))
+                .map_err(|e| SftpError::ssh("Failed to resolve path", e).with_path(path)),
+        )
+    }
+}
+
+// Run a walk decision with ssh2 lookups, which completes on its first poll
+pub fn on_ssh2<T>(decision: impl Future<Output = T>) -> T {
+    decision
+        .now_or_never()
+        .expect("ssh2 walk lookups never wait")
+}
+
+// Decide what to do with the path a walk starts from. A following walk starts
+// from the canonical path, so that every directory it reaches by name has a
+// canonical path as well. Otherwise the root is treated like any entry: when
+// it is a link itself, it is skipped or kept as a link, never walked through.
+pub async fn walk_root<L: WalkLookup>(
+    lookup: &L,
+    path: &str,
+    walk: &SymlinkWalk,
+) -> Result<WalkStep, SftpError> {
+    if walk.policy != SymlinkPolicy::Follow {
+        let stat = lookup.link_stat(path).await?;
+        let top = WalkDir {
+            path: String::new(),
+            ancestors: Vec::new(),
+        };
+        return walk_step(lookup, &top, path, &stat, walk).await;
+    }
+
+    let root = lookup.canonical_path(path).await?;
+    Ok(WalkStep::Directory(WalkDir {
+        path: root.clone(),
+        ancestors: vec![root],
+    }))
+}
+
+// Decide what to do with an entry of `parent` listed by readdir, whose stat
+// describes the entry itself. Directories are always walked into; followed
+// links are skipped when they dangle, loop, or lead back into the directories
+// the walk is inside.
+pub async fn walk_step<L: WalkLookup>(
+    lookup: &L,
+    parent: &WalkDir,
+    path: &str,
+    stat: &FileStat,
+    walk: &SymlinkWalk,
+) -> Result<WalkStep, SftpError> {
+    if !stat.file_type().is_symlink() {
+        if !stat.is_dir() {
+            return Ok(WalkStep::File(stat.clone()));
+        }
+        return Ok(WalkStep::Directory(parent.child(path.to_string(), walk)));
+    }
+
+    match walk.policy {
+        SymlinkPolicy::Skip => Ok(WalkStep::Skip),
+        SymlinkPolicy::AsLink => Ok(WalkStep::Link(stat.clone())),
+        SymlinkPolicy::Follow => {
+            let target = match unless_transport(lookup.target_stat(path).await)? {
+                Some(target) => target,
+                None => return Ok(WalkStep::Skip),
+            };
+            if !target.is_dir() {
+                return Ok(WalkStep::File(target));
+            }
+
+            match unless_transport(lookup.canonical_path(path).await)? {
+                Some(canonical) if !parent.would_cycle(&canonical) => {
+                    Ok(WalkStep::Directory(parent.child(canonical, walk)))
+                }
+                _ => Ok(WalkStep::Skip),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Node {
+        Dir,
+        File,
+        Link(&'static str),
+    }
+
+    // In-memory tree of absolute paths, listed by readdir in insertion order
+    struct FakeFs(Vec<(&'static str, Node)>);
+
+    impl FakeFs {
+        fn node(&self, path: &str) -> Option<&Node> {
+            self.0
+                .iter()
+                .find(|(p, _)| *p == path)
+                .map(|(_, node)| node)
+        }
+
+        fn stat(&self, path: &str) -> Option<FileStat> {
+            let perm = match self.node(path)? {
+                Node::Dir => 0o040755,
+                Node::File => 0o100644,
+                Node::Link(_) => 0o120777,
+            };
+            Some(FileStat {
+                size: Some(1),
+                uid: None,
+                gid: None,
+                perm: Some(perm),
+                atime: None,
+                mtime: None,
+            })
+        }
+
+        // Resolve every link along the path, giving up after a few hops
+        fn resolve(&self, path: &str, hops: &mut usize) -> Option<String> {
+            let mut current = String::new();
+            for component in path.split('/').filter(|c| !c.is_empty()) {
+                let next = format!("{}/{}", current, component);
+                current = match self.node(&next)? {
+                    Node::Link(target) => {
+                        *hops += 1;
+                        if *hops > 8 {
+                            return None;
+                        }
+                        self.resolve(target, hops)?
+                    }
+                    _ => next,
+                };
+            }
+            Some(current)
+        }
+
+        fn children(&self, dir: &str) -> Vec<(String, FileStat)> {
+            self.0
+                .iter()
+                .filter(|(p, _)| p.rsplit_once('/').map(|(parent, _)| parent) == Some(dir))
+                .map(|(p, _)| (p.to_string(), self.stat(p).unwrap()))
+                .collect()
+        }
+    }
+
+    impl WalkLookup for FakeFs {
+        async fn link_stat(&self, path: &str) -> Result<FileStat, SftpError> {
+            self.stat(path)
+                .ok_or_else(|| SftpError::other("No such file"))
+        }
+
+        async fn target_stat(&self, path: &str) -> Result<FileStat, SftpError> {
+            let canonical = self.canonical_path(path).await?;
+            self.link_stat(&canonical).await
+        }
+
+        async fn canonical_path(&self, path: &str) -> Result<String, SftpError> {
+            self.resolve(path, &mut 0)
+                .ok_or_else(|| SftpError::other("Too many links"))
+        }
+    }
+
+    // Walk like a copy does and list the destination paths of what it copies
+    fn copied(fs: &FakeFs, root: &str) -> Vec<String> {
+        fn walk(
+            fs: &FakeFs,
+            dir: &WalkDir,
+            dst: &str,
+            symlinks: &SymlinkWalk,
+            out: &mut Vec<String>,
+        ) {
+            for (path, stat) in fs.children(&dir.path) {
+                let name = path.rsplit('/').next().unwrap();
+                let dst_child = format!("{}{}", dst, name);
+                match on_ssh2(walk_step(fs, dir, &path, &stat, symlinks)).unwrap() {
+                    WalkStep::Directory(subdir) => {
+                        walk(fs, &subdir, &format!("{}/", dst_child), symlinks, out)
+                    }
+                    WalkStep::File(_) | WalkStep::Link(_) => out.push(dst_child),
+                    WalkStep::Skip => {}
+                }
+            }
+        }
+
+        let symlinks = SymlinkWalk::new(SymlinkPolicy::Follow);
+        let mut out = Vec::new();
+        match on_ssh2(walk_root(fs, root, &symlinks)).unwrap() {
+            WalkStep::Directory(root) => walk(fs, &root, "", &symlinks, &mut out),
+            _ => panic!("root is not walked into"),
+        }
+        out
+    }
+
+    #[test]
+    fn loop_links_are_skipped() {
+        let fs = FakeFs(vec![
+            ("/r", Node::Dir),
+            ("/r/a", Node::Link("/r/b")),
+            ("/r/b", Node::Link("/r/a")),
+            ("/r/f", Node::File),
+        ]);
+        assert_eq!(copied(&fs, "/r"), vec!["f"]);
+    }
+
+    #[test]
+    fn links_to_the_walked_directories_are_skipped() {
+        let fs = FakeFs(vec![
+            ("/r", Node::Dir),
+            ("/r/d", Node::Dir),
+            ("/r/d/up", Node::Link("/r")),
+            ("/r/d/here", Node::Link("/r/d")),
+            ("/r/d/top", Node::Link("/")),
+            ("/r/d/f", Node::File),
+        ]);
+        assert_eq!(copied(&fs, "/r"), vec!["d/f"]);
+    }
+
+    #[test]
+    fn link_listed_before_its_target_does_not_hide_it() {
+        let fs = FakeFs(vec![
+            ("/r", Node::Dir),
+            ("/r/a", Node::Link("/r/b")),
+            ("/r/b", Node::Dir),
+            ("/r/b/f", Node::File),
+        ]);
+        assert_eq!(copied(&fs, "/r"), vec!["a/f", "b/f"]);
+    }
+
+    #[test]
+    fn followed_root_link_is_walked_from_its_target() {
+        let fs = FakeFs(vec![
+            ("/r", Node::Link("/s")),
+            ("/s", Node::Dir),
+            ("/s/up", Node::Link("/r")),
+            ("/s/f", Node::File),
+        ]);
+        assert_eq!(copied(&fs, "/r"), vec!["f"]);
+    }
+}
@@ -1,5 +1,11 @@
+use crate::sftp::engine::SftpEngine;
+use crate::sftp::error::SftpError;
+use crate::sftp::owners::OwnerNames;
+use crate::sftp::pool::SftpPool;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, RwLock};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConnectionConfig {
@@ -9,6 +15,39 @@ pub struct ConnectionConfig {
     pub password: Option<String>,
     pub private_key_path: Option<String>,
     pub passphrase: Option<String>,
+    pub use_agent: Option<bool>,
+    pub agent_identity: Option<String>, // Comment or SHA256 fingerprint of the agent key to use
+    pub jump_hosts: Option<Vec<ConnectionConfig>>, // Hops in order, each with its own credentials
+    pub proxy: Option<ProxyConfig>, // Used to reach the first hop (or the target without jump hosts)
+    pub connect_timeout_secs: Option<u64>,
+    pub handshake_timeout_secs: Option<u64>,
+    pub operation_timeout_secs: Option<u64>, // 0 disables the timeout
+    pub sftp_backend: Option<SftpBackend>,
+    pub show_hidden: Option<bool>, // Default for list_directory when the call doesn't say
+    pub max_transfers: Option<usize>, // Transfers run at once on this connection, overriding the queue default
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SftpBackend {
+    Ssh2,  // Blocking ssh2::Sftp, one request at a time (default)
+    Async, // openssh-sftp-client with pipelined requests over a tunnelled channel
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyConfig {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyKind {
+    Socks5,
+    Http,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -19,6 +58,105 @@ pub struct FileItem {
     pub size: u64,
     pub modified: u64, // Unix timestamp
     pub permissions: String,
+    pub is_hidden: bool,                // Dotfile such as .env or .ssh
+    pub is_symlink: bool,               // The other fields describe the link itself, not its target
+    pub link_target: Option<String>,    // Path stored in the link, possibly relative
+    pub target_type: Option<EntryType>, // What the link resolves to, None when broken
+    pub is_broken_link: bool,           // The link dangles, loops or its target can't be read
+    pub mode: u32, // Full st_mode: file type, setuid/setgid/sticky and permission bits
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub owner: Option<String>, // User name of uid, None when the server can't tell
+    pub group: Option<String>,
+    pub accessed: u64, // Unix timestamp
+}
+
+// How recursive copies and size calculations treat symlinks. Deletes never
+// follow links; they remove the link and leave its target alone.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    Skip,   // Leave links out
+    AsLink, // Recreate links on copy, count the link itself on size
+    Follow, // Treat links as their targets, entering each directory once
+}
+
+// A single-file transfer that hasn't finished, persisted so it can be resumed
+// after a failure or a restart. Host, port and username tell which server it
+// belongs to once the connection id of an earlier run means nothing.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferRecord {
+    pub transfer_id: String,
+    pub connection_id: String,
+    pub direction: TransferDirection,
+    pub local_path: String,
+    pub remote_path: String,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+// A transfer that is under way, as listed by get_active_transfers
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActiveTransfer {
+    pub transfer_id: String,
+    pub state: TransferState,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferState {
+    Running,
+    Paused,
+}
+
+// A transfer in the transfer queue, as reported by transfer_queue_changed
+#[derive(Debug, Serialize, Clone)]
+pub struct QueuedTransfer {
+    pub transfer_id: String,
+    pub connection_id: String,
+    #[serde(rename = "type")]
+    pub kind: TransferKind,
+    pub path: String, // Remote path, or the destination of a copy
+    pub state: QueueState,
+    pub error: Option<SftpError>, // Why a failed transfer failed
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferKind {
+    Upload,
+    Download,
+    Copy,
+    Chmod,
+    Chown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueState {
+    Queued, // Waiting for a free slot
+    Running,
+    Paused, // Reported for queued and running transfers whose pause flag is set
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    File,
+    Directory,
+    Symlink,
+    Other, // Devices, sockets and pipes
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,8 +170,169 @@ pub struct TransferProgress {
 pub struct StorageInfo {
     pub total_space: u64,
     pub used_space: u64,
+    pub free_space: u64, // Space available to the user, excluding blocks reserved for root
+    pub total_inodes: Option<u64>, // None when the server can't report inode counts
+    pub free_inodes: Option<u64>,
+    pub mount_point: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KnownHostEntry {
+    pub host: String,
+    pub key_type: String,
+    pub fingerprint: String,
+    pub source: String, // "user" (~/.ssh/known_hosts) or "app" (managed by Fileman)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SshConfigHost {
+    pub alias: String, // Host alias as written in ~/.ssh/config
+    pub config: ConnectionConfig,
 }
 
 // Connection manager to keep track of active connections
 pub type ConnectionId = String;
-pub type ConnectionManager = HashMap<ConnectionId, ssh2::Session>;
+
+// A live session together with the config it was opened with, so it can be re-established
+pub struct Connection {
+    pub sftp_pool: Arc<SftpPool>, // The session and its idle SFTP channels
+    pub async_sftp: Option<Arc<openssh_sftp_client::Sftp>>, // Set when the async backend is selected
+    pub config: ConnectionConfig,
+    pub generation: u64, // Bumped every time a reconnect replaces the session
+    pub owner_names: Arc<OwnerNames>, // Kept across reconnects, it's the same server
+    // Held while the session is re-established, so failures that happen at
+    // once reconnect (and prompt the user) only one time
+    pub reconnecting: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Connection {
+    // The backend SFTP traffic of this connection goes through
+    pub fn engine(&self) -> SftpEngine {
+        match &self.async_sftp {
+            Some(sftp) => SftpEngine::Async(sftp.clone()),
+            None => SftpEngine::Ssh2(self.sftp_pool.clone()),
+        }
+    }
+}
+
+// Session and optional async SFTP client produced by connecting once
+pub struct EstablishedConnection {
+    pub session: ssh2::Session,
+    pub async_sftp: Option<Arc<openssh_sftp_client::Sftp>>,
+}
+
+// Registry of live connections. The map lock is only held to look a connection
+// up; each connection has its own lock, held just long enough to read or swap
+// its session, so blocking I/O on one server never stalls another.
+#[derive(Default)]
+pub struct ConnectionManager {
+    connections: RwLock<HashMap<ConnectionId, Arc<Mutex<Connection>>>>,
+    // Responders for keyboard-interactive prompts of connections that are still authenticating
+    pending_auth: Mutex<HashMap<ConnectionId, Sender<Vec<String>>>>,
+}
+
+impl ConnectionManager {
+    pub fn new() -> Self {
+        ConnectionManager {
+            connections: RwLock::new(HashMap::new()),
+            pending_auth: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, connection_id: &str) -> Option<Arc<Mutex<Connection>>> {
+        self.connections.read().unwrap().get(connection_id).cloned()
+    }
+
+    // Config a connection was opened with
+    pub fn config(&self, connection_id: &str) -> Option<ConnectionConfig> {
+        let connection = self.get(connection_id)?;
+        let config = connection.lock().unwrap().config.clone();
+        Some(config)
+    }
+
+    // SFTP channel pool of a connection's current session along with its generation
+    pub fn sftp_pool(&self, connection_id: &str) -> Option<(Arc<SftpPool>, u64)> {
+        let connection = self.get(connection_id)?;
+        let connection = connection.lock().unwrap();
+        Some((connection.sftp_pool.clone(), connection.generation))
+    }
+
+    // Cached user and group names of a connection's server
+    pub fn owner_names(&self, connection_id: &str) -> Option<Arc<OwnerNames>> {
+        let connection = self.get(connection_id)?;
+        let owner_names = connection.lock().unwrap().owner_names.clone();
+        Some(owner_names)
+    }
+
+    // Current SFTP engine of a connection along with its generation
+    pub fn engine(&self, connection_id: &str) -> Option<(SftpEngine, u64)> {
+        let connection = self.get(connection_id)?;
+        let connection = connection.lock().unwrap();
+        Some((connection.engine(), connection.generation))
+    }
+
+    pub fn insert(
+        &self,
+        connection_id: ConnectionId,
+        established: EstablishedConnection,
+        config: ConnectionConfig,
+    ) {
+        self.connections.write().unwrap().insert(
+            connection_id,
+            Arc::new(Mutex::new(Connection {
+                sftp_pool: Arc::new(SftpPool::new(established.session)),
+                async_sftp: established.async_sftp,
+                config,
+                generation: 0,
+                owner_names: Arc::new(OwnerNames::default()),
+                reconnecting: Arc::new(tokio::sync::Mutex::new(())),
+            })),
+        );
+    }
+
+    // Swap in a re-established connection, returning its channel pool and
+    // generation, or None if the connection was removed in the meantime.
+    // Channels of the old session are released.
+    pub fn replace_session(
+        &self,
+        connection_id: &str,
+        established: EstablishedConnection,
+    ) -> Option<(Arc<SftpPool>, u64)> {
+        let connection = self.get(connection_id)?;
+        let mut connection = connection.lock().unwrap();
+        connection.sftp_pool.close();
+        connection.sftp_pool = Arc::new(SftpPool::new(established.session));
+        connection.async_sftp = established.async_sftp;
+        connection.generation += 1;
+        Some((connection.sftp_pool.clone(), connection.generation))
+    }
+
+    // Forget a connection and release its SFTP channels, returning the session to disconnect
+    pub fn remove(&self, connection_id: &str) -> Option<ssh2::Session> {
+        let connection = self.connections.write().unwrap().remove(connection_id)?;
+        let connection = connection.lock().unwrap();
+        connection.sftp_pool.close();
+        Some(connection.sftp_pool.session().clone())
+    }
+
+    // Snapshot of every connection, taken without holding the map lock afterwards
+    pub fn all(&self) -> Vec<(ConnectionId, Arc<Mutex<Connection>>)> {
+        self.connections
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(connection_id, connection)| (connection_id.clone(), connection.clone()))
+            .collect()
+    }
+
+    pub fn set_pending_auth(&self, connection_id: &str, responder: Sender<Vec<String>>) {
+        self.pending_auth
+            .lock()
+            .unwrap()
+            .insert(connection_id.to_string(), responder);
+    }
+
+    pub fn take_pending_auth(&self, connection_id: &str) -> Option<Sender<Vec<String>>> {
+        self.pending_auth.lock().unwrap().remove(connection_id)
+    }
+}
@@ -5,6 +5,7 @@ import Layout from "./layout/layout";
 import { Toaster } from "./components/ui/sonner";
 import { useEffect } from "react";
 import useSessionStore from "./stores/session.store";
+import KeyboardInteractiveDialog from "./dialogs/keyboard-interactive.dialog";
 
 function App() {
   enableMapSet();
@@ -21,6 +22,7 @@ function App() {
     <ThemeProvider defaultTheme="dark" storageKey="vite-ui-theme">
       <Layout />
       <Toaster />
+      <KeyboardInteractiveDialog />
     </ThemeProvider>
   );
 }
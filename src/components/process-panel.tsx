@@ -6,12 +6,13 @@ import {
     DropdownMenuSeparator,
     DropdownMenuTrigger,
 } from "@/components/ui/dropdown-menu"
+import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { useEffect, useState } from "react";
 import ProcessProgress from "./process-progress";
 import { Badge } from "./ui/badge";
 import useProcessStore from "@/stores/process.store";
-import { Process } from "@/types/process";
+import { Process, QueuedTransfer } from "@/types/process";
 import { toast } from "sonner";
 import { Bell } from "lucide-react";
 import useTabStore from "@/stores/tab.store";
@@ -25,98 +26,63 @@ export default function ProcessPanel() {
 
     // Store hooks
     const autoClearSuccessNotifications = useConfigStore((state) => state.autoClearSuccessNotifications);
-    const addProcess = useProcessStore((state) => state.addProcess);
     const updateProcess = useProcessStore((state) => state.updateProcess);
-    const removeProcess = useProcessStore((state) => state.removeProcess);
+    const syncQueue = useProcessStore((state) => state.syncQueue);
 
     useEffect(() => {
-        // Register listeners for upload progress and transfer cancellation
-        const unlistenUpload = listen<Process>("upload_progress", (event) => {
-            const { connection_id, path, transferred, total, transfer_id, type } = event.payload;
-            const latestProcesses = useProcessStore.getState().processes;
-            const existingProcess = latestProcesses.find(p => p.transfer_id === transfer_id);
-
-            if (existingProcess) {
-                updateProcess(transfer_id, { transferred, total, status: "active", type, path, connection_id });
-            } else {
-                addProcess({
-                    connection_id,
-                    path,
-                    transferred,
-                    total,
-                    transfer_id,
-                    status: "active",
-                    type,
-                } as Process);
-
-                // Show process panel
+        // The transfer queue decides which processes are listed and their status
+        const applyQueue = (entries: QueuedTransfer[]) => {
+            const known = useProcessStore.getState().processes;
+            const added = entries.some(entry => !known.some(p => p.transfer_id === entry.transfer_id));
+            syncQueue(entries);
+
+            // Show process panel for new transfers
+            if (added) {
                 setShowPanel(true);
             }
+        };
+        invoke<QueuedTransfer[]>("get_transfer_queue")
+            .then(syncQueue)
+            .catch((error) => console.error("Failed to load transfer queue:", error));
+        const unlistenQueue = listen<QueuedTransfer[]>("transfer_queue_changed", (event) => {
+            applyQueue(event.payload);
+        });
+
+        // Register listeners for transfer progress
+        const unlistenUpload = listen<Process>("upload_progress", (event) => {
+            const { path, transfer_id, files_completed, files_total } = event.payload;
+            // Directory uploads show the progress of the whole tree
+            const transferred = event.payload.tree_transferred ?? event.payload.transferred;
+            const total = event.payload.tree_total ?? event.payload.total;
+            updateProcess(transfer_id, { transferred, total, path, files_completed, files_total });
         });
 
         const unlistenDownload = listen<Process>("download_progress", (event) => {
-            const { connection_id, path, transferred, total, transfer_id, type } = event.payload;
-            const latestProcesses = useProcessStore.getState().processes;
-            const existingProcess = latestProcesses.find(p => p.transfer_id === transfer_id);
-
-            if (existingProcess) {
-                updateProcess(transfer_id, { transferred, total, status: "active", type, path, connection_id });
-            } else {
-                addProcess({
-                    connection_id,
-                    path,
-                    transferred,
-                    total,
-                    transfer_id,
-                    status: "active",
-                    type,
-                } as Process);
-
-                // Show process panel
-                setShowPanel(true);
-            }
+            const { path, transfer_id, files_completed, files_total } = event.payload;
+            // Directory downloads show the progress of the whole tree
+            const transferred = event.payload.tree_transferred ?? event.payload.transferred;
+            const total = event.payload.tree_total ?? event.payload.total;
+            updateProcess(transfer_id, { transferred, total, path, files_completed, files_total });
         });
 
         const unlistenCopyProgress = listen<Process>("copy_progress", (event) => {
-            const { connection_id, path, transferred, total, transfer_id, type } = event.payload;
-            const latestProcesses = useProcessStore.getState().processes;
-            const existingProcess = latestProcesses.find(p => p.transfer_id === transfer_id);
-
-            if (existingProcess) {
-                updateProcess(transfer_id, { transferred, total, status: "active", type, path, connection_id });
-            } else {
-                addProcess({
-                    connection_id,
-                    path,
-                    transferred,
-                    total,
-                    transfer_id,
-                    status: "active",
-                    type,
-                } as Process);
-
-                // Show process panel
-                setShowPanel(true);
-            }
+            const { path, transferred, total, transfer_id } = event.payload;
+            updateProcess(transfer_id, { transferred, total, path });
         });
 
         // Listen for transfer cancellation
         const unlistenCancel = listen<{ transfer_id: string; type: string }>("transfer_cancelled", (event) => {
-            const { transfer_id } = event.payload;
-            updateProcess(transfer_id, { status: "cancelled" });
-            // removeProcess(transfer_id);
             toast.error("Transfer cancelled");
         });
 
         // Listen for transfer completion
         const unlistenComplete = listen<{ transfer_id: string; type: string, path: string }>("process_finished", (event) => {
             const { transfer_id, path } = event.payload;
-            updateProcess(transfer_id, { status: "completed" });
 
             // Auto clear success notifications if enabled
             if (autoClearSuccessNotifications) {
                 setTimeout(() => {
-                    removeProcess(transfer_id);
+                    invoke("remove_transfer", { transferId: transfer_id }).catch(() => {});
                 }, 2000); // Clear after 2 seconds
             }
 
@@ -144,6 +110,7 @@ export default function ProcessPanel() {
         });
 
         return () => {
+            unlistenQueue.then(unsub => unsub());
             unlistenUpload.then(unsub => unsub());
             unlistenDownload.then(unsub => unsub());
             unlistenCancel.then(unsub => unsub());
@@ -177,6 +144,7 @@ export default function ProcessPanel() {
                             type={process.type}
                             transferId={process.transfer_id}
                             status={process.status}
+                            error={process.error}
                         />
                     ))}
 
@@ -195,13 +163,13 @@ export default function ProcessPanel() {
                         variant="secondary"
                         size="sm"
                         disabled={processes.length === 0}
-                        onClick={() => {
-                            // Clear all completed processes
-                            processes.forEach(process => {
-                                if (process.status === "completed" || process.status === "cancelled") {
-                                    removeProcess(process.transfer_id);
-                                }
-                            });
+                        onClick={async () => {
+                            // Clear all finished processes
+                            try {
+                                await invoke("clear_finished_transfers");
+                            } catch (error) {
+                                console.error("Failed to clear transfers:", error);
+                            }
                         }}
                         aria-label="Clear completed processes"
                     >
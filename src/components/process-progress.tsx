@@ -1,23 +1,40 @@
-import { Copy, Download, Upload, XIcon } from "lucide-react"
+import { ArrowUpToLine, Copy, Download, Pause, Play, ShieldCheck, Upload, UserCog, XIcon } from "lucide-react"
 
 import { Button } from "@/components/ui/button"
 import { bytesSizeToString } from "@/utils/file.util";
 import { invoke } from "@tauri-apps/api/core";
 import { toast } from "sonner";
 import { Badge } from "./ui/badge";
-import useProcessStore from "@/stores/process.store";
+import { Process } from "@/types/process";
+
+const activeLabels: Record<Process["type"], string> = {
+    upload: "Uploading",
+    download: "Downloading",
+    copy: "Copying",
+    chmod: "Changing permissions",
+    chown: "Changing owner",
+};
+
+const doneLabels: Record<Process["type"], string> = {
+    upload: "Upload",
+    download: "Download",
+    copy: "Copy",
+    chmod: "Permission change",
+    chown: "Owner change",
+};
 
 type Props = {
     connectionId: string;
     path: string;
     transferred: number;
     total: number;
-    type: "upload" | "download" | "copy";
+    type: Process["type"];
     transferId: string;
-    status: "active" | "cancelled" | "completed";
+    status: Process["status"];
+    error?: string;
 }
 
-export default function ProcessProgress({ path, transferred, total, type, transferId, status }: Props) {
+export default function ProcessProgress({ path, transferred, total, type, transferId, status, error }: Props) {
     // Calculate progress percentage
     const progress = total > 0 ? Math.round((transferred / total) * 100) : 0;
     const totalSize = bytesSizeToString(total);
@@ -25,7 +42,6 @@ export default function ProcessProgress({ path, transferred, total, type, transf
 
     // Filename for the notification
     const filename = path.split('/').pop() || "File";
-    const removeProcess = useProcessStore((state) => state.removeProcess);
 
     // Render
     return (
@@ -40,6 +56,10 @@ export default function ProcessProgress({ path, transferred, total, type, transf
                         <Upload size={16} className={status === "active" ? "text-blue-500" : "text-gray-500"} />
                     ) : type === "download" ? (
                         <Download size={16} className={status === "active" ? "text-green-500" : "text-gray-500"} />
+                    ) : type === "chmod" ? (
+                        <ShieldCheck size={16} className={status === "active" ? "text-purple-500" : "text-gray-500"} />
+                    ) : type === "chown" ? (
+                        <UserCog size={16} className={status === "active" ? "text-purple-500" : "text-gray-500"} />
                     ) : (
                         <Copy size={16} className={status === "active" ? "text-yellow-500" : "text-gray-500"} />
                     )}
@@ -58,7 +78,21 @@ export default function ProcessProgress({ path, transferred, total, type, transf
                                     className="text-xs text-muted-foreground"
                                     variant="secondary"
                                 >
-                                    {type === "upload" ? "Uploading" : type === "download" ? "Downloading" : "Copying"}
+                                    {activeLabels[type]}
+                                </Badge>
+                            ) : status === "queued" ? (
+                                <Badge
+                                    className="text-xs text-muted-foreground"
+                                    variant="outline"
+                                >
+                                    Queued
+                                </Badge>
+                            ) : status === "paused" ? (
+                                <Badge
+                                    className="text-xs text-muted-foreground"
+                                    variant="outline"
+                                >
+                                    Paused
                                 </Badge>
                             ) : status === "cancelled" ? (
                                 <Badge
@@ -67,12 +101,20 @@ export default function ProcessProgress({ path, transferred, total, type, transf
                                 >
                                     Cancelled
                                 </Badge>
+                            ) : status === "failed" ? (
+                                <Badge
+                                    className="text-xs"
+                                    variant="destructive"
+                                    title={error}
+                                >
+                                    Failed
+                                </Badge>
                             ) : (
                                 <Badge
                                     className="text-xs"
                                     variant="default"
                                 >
-                                    {type === "upload" ? "Upload" : type === "download" ? "Download" : "Copy"} Completed
+                                    {doneLabels[type]} Completed
                                 </Badge>
                             )
                         }
@@ -80,8 +122,50 @@ export default function ProcessProgress({ path, transferred, total, type, transf
                     {/* <Button size="sm">Notify me</Button> */}
                 </div>
 
+                {/* Prioritize Button */}
+                {status === "queued" && (
+                    <Button
+                        variant="ghost"
+                        className="group -my-1.5 size-8 shrink-0 p-0 hover:bg-transparent"
+                        aria-label="Start next"
+                        onClick={async () => {
+                            try {
+                                await invoke("prioritize_transfer", { transferId: transferId });
+                            } catch (error) {
+                                console.error("Failed to prioritize transfer:", error);
+                                toast.error("Failed to prioritize transfer");
+                            }
+                        }}
+                    >
+                        <ArrowUpToLine size={16} className="opacity-60 transition-opacity group-hover:opacity-100" aria-hidden="true" />
+                    </Button>
+                )}
+
+                {/* Pause / Resume Button */}
+                {(status === "queued" || status === "active" || status === "paused") && (
+                    <Button
+                        variant="ghost"
+                        className="group -my-1.5 size-8 shrink-0 p-0 hover:bg-transparent"
+                        aria-label={status === "paused" ? "Resume transfer" : "Pause transfer"}
+                        onClick={async () => {
+                            try {
+                                await invoke(status === "paused" ? "resume_transfer" : "pause_transfer", { transferId: transferId });
+                            } catch (error) {
+                                console.error("Failed to pause or resume transfer:", error);
+                                toast.error(status === "paused" ? "Failed to resume transfer" : "Failed to pause transfer");
+                            }
+                        }}
+                    >
+                        {status === "paused" ? (
+                            <Play size={16} className="opacity-60 transition-opacity group-hover:opacity-100" aria-hidden="true" />
+                        ) : (
+                            <Pause size={16} className="opacity-60 transition-opacity group-hover:opacity-100" aria-hidden="true" />
+                        )}
+                    </Button>
+                )}
+
                 {/* Cancel Button */}
-                {status === "active" && (
+                {(status === "queued" || status === "active" || status === "paused") && (
                     <Button
                         variant="ghost"
                         className="group -my-1.5 -me-2 size-8 shrink-0 p-0 hover:bg-transparent"
@@ -106,13 +190,17 @@ export default function ProcessProgress({ path, transferred, total, type, transf
                 )}
 
                 {/* Clear Button */}
-                {(status === "completed" || status === "cancelled") && (
+                {(status === "completed" || status === "cancelled" || status === "failed") && (
                     <Button
                         variant="secondary"
                         size="xsm"
-                        onClick={() => {
-                            // Clear the process from the store
-                            removeProcess(transferId);
+                        onClick={async () => {
+                            // Clear the process from the queue
+                            try {
+                                await invoke("remove_transfer", { transferId: transferId });
+                            } catch (error) {
+                                console.error("Failed to clear transfer:", error);
+                            }
                         }}
                         aria-label="Clear process"
                     >
@@ -7,11 +7,13 @@ import { listen } from "@tauri-apps/api/event";
 import { Info, Loader2 } from "lucide-react";
 import { useEffect, useState } from "react";
 import { toast } from "sonner";
+import { SftpError } from "@/types/SftpError";
+import { errorMessage } from "@/utils/error.util";
 
 type DirectorySizeCompletedEvent = {
     operation_id: string;
     path: string;
-    error: string | null;
+    error: SftpError | null;
     success: boolean;
     size: number | null;
 };
@@ -73,7 +75,7 @@ export default function FileInfoDialog({ dialogOpen, onOpenChange, file }: Props
             if (success) {
                 setFileSize(size);
             } else {
-                toast.error(`Failed to fetch directory size for ${path}: ${error}`);
+                toast.error(`Failed to fetch directory size for ${path}: ${errorMessage(error)}`);
             }
 
             setFetchingInfo(null);
@@ -122,7 +124,27 @@ export default function FileInfoDialog({ dialogOpen, onOpenChange, file }: Props
                                 <div className="px-2 py-2 border-b flex flex-row">
                                     <span>File Type: </span>
                                     <span className="pl-2">
-                                        {file?.is_directory ? "Directory" : "File"}
+                                        {file?.is_symlink
+                                            ? `Symlink to ${file.link_target ?? "unknown"}${file.is_broken_link ? " (broken)" : ""}`
+                                            : file?.is_directory ? "Directory" : "File"}
+                                    </span>
+                                </div>
+                                <div className="px-2 py-2 border-b flex flex-row">
+                                    <span>Owner: </span>
+                                    <span className="pl-2">
+                                        {file?.owner ?? file?.uid ?? "Unknown"}:{file?.group ?? file?.gid ?? "Unknown"}
+                                    </span>
+                                </div>
+                                <div className="px-2 py-2 border-b flex flex-row">
+                                    <span>Permissions: </span>
+                                    <span className="pl-2 font-mono">
+                                        {file?.permissions} ({((file?.mode ?? 0) & 0o7777).toString(8).padStart(4, "0")})
+                                    </span>
+                                </div>
+                                <div className="px-2 py-2 border-b flex flex-row">
+                                    <span>Last Accessed: </span>
+                                    <span className="pl-2">
+                                        {file?.accessed ? dateTimeFromTimestamp(file?.accessed) : "Unknown"}
                                     </span>
                                 </div>
                                 <div className="px-2 py-2 flex flex-row">
@@ -0,0 +1,101 @@
+import { KeyRound } from "lucide-react"
+
+import { Button } from "@/components/ui/button"
+import {
+    Dialog,
+    DialogContent,
+    DialogDescription,
+    DialogHeader,
+    DialogTitle,
+} from "@/components/ui/dialog"
+import { Input } from "@/components/ui/input"
+import { Label } from "@/components/ui/label"
+import { listen } from "@tauri-apps/api/event"
+import { useEffect, useState } from "react"
+import useSessionStore from "@/stores/session.store"
+import { KeyboardInteractivePrompt } from "@/types/KeyboardInteractivePrompt"
+
+// Asks the questions of keyboard-interactive logins (passwords, OTP codes, ...)
+// while a connection or reconnect waits for the answers
+export default function KeyboardInteractiveDialog() {
+    // Store hooks
+    const prompt = useSessionStore((state) => state.authPrompts[0]);
+    const addAuthPrompt = useSessionStore((state) => state.addAuthPrompt);
+    const respondToAuthPrompt = useSessionStore((state) => state.respondToAuthPrompt);
+
+    // State
+    const [answers, setAnswers] = useState<string[]>([]);
+
+    useEffect(() => {
+        const unlisten = listen<KeyboardInteractivePrompt>("keyboard_interactive_prompt", (event) => {
+            addAuthPrompt(event.payload);
+        });
+
+        return () => {
+            unlisten.then(unsub => unsub());
+        };
+    }, []);
+
+    // Start with empty answers for every new prompt
+    useEffect(() => {
+        setAnswers(prompt ? prompt.prompts.map(() => "") : []);
+    }, [prompt]);
+
+    // Render
+    return (
+        <Dialog
+            open={!!prompt}
+            onOpenChange={(open) => {
+                // Closing the dialog rejects the prompt
+                if (!open) respondToAuthPrompt([]);
+            }}
+        >
+            <DialogContent>
+                <div className="mb-2 flex flex-col items-center gap-2">
+                    <div
+                        className="flex size-11 shrink-0 items-center justify-center rounded-full border"
+                        aria-hidden="true"
+                    >
+                        <KeyRound size={16} className="opacity-80" />
+                    </div>
+                    <DialogHeader>
+                        <DialogTitle className="sm:text-center">
+                            Authentication required
+                        </DialogTitle>
+                        <DialogDescription className="sm:text-center">
+                            {prompt?.username}@{prompt?.host}
+                            {prompt?.instructions && <><br />{prompt.instructions}</>}
+                        </DialogDescription>
+                    </DialogHeader>
+                </div>
+
+                <form className="space-y-5" onSubmit={(e) => {
+                    e.preventDefault();
+                    respondToAuthPrompt(answers);
+                }}>
+                    {prompt?.prompts.map((question, index) => (
+                        <div key={index} className="*:not-first:mt-2">
+                            <Label htmlFor={`auth-prompt-${index}`} className="text-sm font-normal text-muted-foreground px-1">
+                                {question.text}
+                            </Label>
+                            <Input
+                                id={`auth-prompt-${index}`}
+                                type={question.echo ? "text" : "password"}
+                                value={answers[index] ?? ""}
+                                onChange={(e) => {
+                                    const next = [...answers];
+                                    next[index] = e.target.value;
+                                    setAnswers(next);
+                                }}
+                                autoFocus={index === 0}
+                            />
+                        </div>
+                    ))}
+                    <Button type="submit" className="w-full">
+                        Continue
+                    </Button>
+                </form>
+            </DialogContent>
+        </Dialog>
+    )
+}
@@ -11,6 +11,7 @@ import { useState } from "react";
 import { toast } from "sonner";
 import { open } from "@tauri-apps/plugin-dialog";
 import { Label } from "@/components/ui/label";
+import { errorMessage } from "@/utils/error.util";
 
 type Props = {
     dialogOpen: boolean;
@@ -95,7 +96,7 @@ export default function SessionWizardDialog({ dialogOpen, onOpenChange, trigger
                 // useTabStore.getState().createTab(newSession, "/");
             })
             .catch((error) => {
-                toast.error(`Failed to connect to session: ${error.message}`);
+                toast.error(`Failed to connect to session: ${errorMessage(error)}`);
             });
     }
 
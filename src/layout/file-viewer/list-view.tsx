@@ -42,8 +42,8 @@ export default function ListView({ tabId, onDelete, onFileInfo, onRenameFile, on
             return;
         }
 
-        // If file is not a directory, don't navigate
-        if (!file.is_directory) {
+        // If file is not a directory (or a link to one), don't navigate
+        if (!file.is_directory && file.target_type !== "directory") {
             toast.error("Cannot open files, only directories can be navigated.");
             return;
         }
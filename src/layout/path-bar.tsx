@@ -2,7 +2,7 @@ import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import useSessionStore from "@/stores/session.store";
 import useTabStore from "@/stores/tab.store";
-import { ArrowLeft, ArrowRight, ClipboardIcon, Download, EllipsisIcon, FolderPlus, FolderUp, Loader2, RefreshCcw, UploadCloud } from "lucide-react";
+import { ArrowLeft, ArrowRight, ClipboardIcon, Download, EllipsisIcon, Eye, EyeOff, FolderPlus, FolderUp, Loader2, RefreshCcw, UploadCloud } from "lucide-react";
 import { useState } from "react";
 import CreateFolderDialog from "@/dialogs/create-folder.dialog";
 import { toast } from "sonner";
@@ -42,6 +42,10 @@ export default function PathBar() {
     const closeTab = useTabStore((state) => state.closeTab);
     const selectFile = useTabStore((state) => state.selectFile);
     const pasteClipboardItems = useClipboardStore((state) => state.paste);
+    const setShowHidden = useTabStore((state) => state.setShowHidden);
+
+    // The tab's choice, else the session default
+    const showHidden = tab?.showHidden ?? session?.showHidden ?? false;
 
     // Current session clipboard
     const clipboardItems = useClipboardStore((state) => state.items);
@@ -358,6 +362,26 @@ export default function PathBar() {
                 >
                     <RefreshCcw size={16} aria-hidden="true" />
                 </Button>
+                <Button
+                    className="rounded-none shadow-none first:rounded-s-md last:rounded-e-md focus-visible:z-10"
+                    variant="outline"
+                    size="iconSm"
+                    aria-label={showHidden ? "Hide hidden files" : "Show hidden files"}
+                    title={showHidden ? "Hide hidden files" : "Show hidden files"}
+                    onClick={() => {
+                        if (!tabId) {
+                            return;
+                        }
+                        setShowHidden(tabId, !showHidden);
+                    }}
+                    disabled={!session || session.status !== 'connected'}
+                >
+                    {showHidden ? (
+                        <Eye size={16} aria-hidden="true" />
+                    ) : (
+                        <EyeOff size={16} aria-hidden="true" />
+                    )}
+                </Button>
             </div>
 
             {/* Path Input */}
@@ -7,6 +7,11 @@ export default interface ISession {
   password?: string; // Optional for passwordless authentication
   privateKeyPath?: string; // Optional for key-based authentication
   passphrase?: string; // Optional passphrase for private key
+  useAgent?: boolean; // Authenticate with identities from the running ssh-agent
+  agentIdentity?: string; // Optional comment or fingerprint of the agent key to use
+  sftpBackend?: "ssh2" | "async"; // SFTP implementation used for listing and transfers
+  showHidden?: boolean; // List dotfiles by default
+  maxTransfers?: number; // Optional limit on transfers running at once on this connection
   createdAt: Date;
   updatedAt: Date;
   lastUsedAt?: Date; // Optional field to track the last time the session was used
@@ -38,6 +38,7 @@ export default interface ITab {
   sortBy: SortBy; // Current sort column
   sortOrder: SortOrder; // Sort direction
   filterQuery: string; // Search/filter text
+  showHidden?: boolean; // List dotfiles, overriding the session default when set
   scrollPosition: number; // Remember scroll position
 
   // Transfer state
@@ -1,4 +1,4 @@
-import { Process } from "@/types/process";
+import { Process, QueuedTransfer } from "@/types/process";
 import { create } from "zustand";
 import { devtools, persist } from "zustand/middleware";
 import { immer } from "zustand/middleware/immer";
@@ -9,6 +9,7 @@ interface ProcessStore {
   addProcess: (process: Process) => void;
   updateProcess: (transferId: string, updates: Partial<Process>) => void;
   removeProcess: (transferId: string) => void;
+  syncQueue: (entries: QueuedTransfer[]) => void;
 }
 
 const useProcessStore = create<ProcessStore>()(
@@ -42,6 +43,25 @@ const useProcessStore = create<ProcessStore>()(
             state.processes = state.processes.filter((p) => p.transfer_id !== transferId);
           });
         },
+        syncQueue: (entries: QueuedTransfer[]) => {
+          // The queue decides which processes exist, their order and status; progress is kept
+          set((state) => {
+            state.processes = entries.map((entry): Process => {
+              const existingProcess = state.processes.find((p) => p.transfer_id === entry.transfer_id);
+              return {
+                transferred: 0,
+                total: 0,
+                ...existingProcess,
+                connection_id: entry.connection_id,
+                path: existingProcess?.path ?? entry.path,
+                type: entry.type,
+                transfer_id: entry.transfer_id,
+                status: entry.state === "running" ? "active" : entry.state,
+                error: entry.error?.message,
+              };
+            });
+          });
+        },
       })),
       { name: "process-store" }
     )
@@ -2,9 +2,13 @@ import { create } from "zustand";
 import { devtools, persist } from "zustand/middleware";
 import { immer } from "zustand/middleware/immer";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { ask } from "@tauri-apps/plugin-dialog";
 import ISession from "../models/session.model";
 import { ConnectionState } from "../types/ConnectionState";
+import { KeyboardInteractivePrompt } from "../types/KeyboardInteractivePrompt";
 import { FileItem } from "../types/FileItem";
+import { errorMessage, isSftpError } from "@/utils/error.util";
 
 const ENCRYPTION_KEY = import.meta.env.VITE_ENCRYPTION_KEY;
 
@@ -17,6 +21,7 @@ interface SessionStore {
   // UI state
   isLoading: boolean;
   error?: string;
+  authPrompts: KeyboardInteractivePrompt[]; // Keyboard-interactive prompts waiting for answers, oldest first
 
   // Session CRUD operations
   addSession: (session: Omit<ISession, "id" | "createdAt" | "updatedAt">) => Promise<string | undefined>;
@@ -34,6 +39,8 @@ interface SessionStore {
   connectToSession: (sessionId: string) => Promise<boolean>;
   disconnectSession: (sessionId: string) => Promise<void>;
   disconnectAllSessions: () => Promise<void>;
+  addAuthPrompt: (prompt: KeyboardInteractivePrompt) => void;
+  respondToAuthPrompt: (responses: string[]) => Promise<void>;
 
   // File operations
   loadDirectory: (sessionId: string, path: string) => Promise<void>;
@@ -177,6 +184,45 @@ async function decrypt(cipher?: string): Promise<string | undefined> {
   }
 }
 
+// Host key a server offered that isn't trusted yet, from host_key_unknown
+type HostKeyOffer = {
+  host: string;
+  port: number;
+  key_type: string;
+  fingerprint: string;
+};
+
+// Catch the host_key_unknown event of host:port. Registered before connecting,
+// since the event can reach the webview after the rejected connect does.
+async function listenForHostKey(host: string, port: number) {
+  let resolveOffer: (offer: HostKeyOffer) => void = () => {};
+  const offer = new Promise<HostKeyOffer>((resolve) => (resolveOffer = resolve));
+  const unlisten = await listen<HostKeyOffer>("host_key_unknown", (event) => {
+    if (event.payload.host === host && event.payload.port === port) {
+      resolveOffer(event.payload);
+    }
+  });
+  return { offer, unlisten };
+}
+
+// Show the fingerprint of a host key seen for the first time and trust it if
+// the user agrees. Returns whether the key is now trusted.
+async function trustHostKey(offer: Promise<HostKeyOffer>): Promise<boolean> {
+  const key = await Promise.race([offer, new Promise<undefined>((resolve) => setTimeout(resolve, 2000))]);
+  if (!key) return false;
+
+  const trusted = await ask(
+    `The authenticity of host ${key.host}:${key.port} can't be established.\n\n` +
+      `${key.key_type} key fingerprint is ${key.fingerprint}.\n\n` +
+      "Trust this host and connect?",
+    { title: "Unknown host key", kind: "warning" }
+  );
+  if (!trusted) return false;
+
+  await invoke("accept_host_key", { host: key.host, port: key.port, fingerprint: key.fingerprint });
+  return true;
+}
+
 const useSessionStore = create<SessionStore>()(
   devtools(
     persist(
@@ -187,6 +233,7 @@ const useSessionStore = create<SessionStore>()(
         selectedSessionId: undefined,
         isLoading: false,
         error: undefined,
+        authPrompts: [],
 
         // Session CRUD operations
         addSession: async (sessionData) => {
@@ -272,7 +319,7 @@ const useSessionStore = create<SessionStore>()(
               } catch (error) {
                 console.error("Error disconnecting session:", error);
                 set((state) => {
-                  state.error = `Failed to disconnect session: ${error}`;
+                  state.error = `Failed to disconnect session: ${errorMessage(error)}`;
                 });
               }
             }
@@ -293,7 +340,7 @@ const useSessionStore = create<SessionStore>()(
           } catch (error) {
             console.error("Error deleting session:", error);
             set((state) => {
-              state.error = `Failed to delete session: ${error}`;
+              state.error = `Failed to delete session: ${errorMessage(error)}`;
             });
             return false; // Indicate failure
           }
@@ -369,6 +416,9 @@ const useSessionStore = create<SessionStore>()(
             state.error = undefined;
           });
 
+          // Unknown host keys are offered to the user, see trustHostKey
+          const hostKey = await listenForHostKey(session.host, session.port);
+
           try {
             // Update status to connecting
             get().updateSessionStatus(sessionId, "connecting");
@@ -386,6 +436,11 @@ const useSessionStore = create<SessionStore>()(
                 password: decryptedPassword,
                 private_key_path: session.privateKeyPath,
                 passphrase: decryptedPassphrase,
+                use_agent: session.useAgent,
+                agent_identity: session.agentIdentity,
+                sftp_backend: session.sftpBackend,
+                show_hidden: session.showHidden,
+                max_transfers: session.maxTransfers,
               },
             });
 
@@ -411,14 +466,27 @@ const useSessionStore = create<SessionStore>()(
 
             return true; // Connection successful
           } catch (error) {
+            // Connect again once the user trusts the server's key
+            if (isSftpError(error) && error.kind === "host_key_unknown") {
+              try {
+                if (await trustHostKey(hostKey.offer)) {
+                  return get().connectToSession(sessionId);
+                }
+              } catch (trustError) {
+                error = trustError;
+              }
+            }
+
             console.error("Connection failed:", error);
             set((state) => {
-              state.error = `Connection failed: ${error}`;
+              state.error = `Connection failed: ${errorMessage(error)}`;
               state.isLoading = false;
             });
             get().updateSessionStatus(sessionId, "disconnected");
 
             return false; // Connection failed
+          } finally {
+            hostKey.unlisten();
           }
         },
 
@@ -439,7 +507,7 @@ const useSessionStore = create<SessionStore>()(
           } catch (error) {
             console.error("Disconnect failed:", error);
             set((state) => {
-              state.error = `Disconnect failed: ${error}`;
+              state.error = `Disconnect failed: ${errorMessage(error)}`;
             });
           }
         },
@@ -458,6 +526,31 @@ const useSessionStore = create<SessionStore>()(
           console.log("All sessions disconnected");
         },
 
+        addAuthPrompt: (prompt) => {
+          set((state) => {
+            state.authPrompts.push(prompt);
+          });
+        },
+
+        // Answer the oldest prompt; no answers rejects it, so the login fails right away
+        respondToAuthPrompt: async (responses) => {
+          const prompt = get().authPrompts[0];
+          if (!prompt) return;
+
+          set((state) => {
+            state.authPrompts.shift();
+          });
+
+          try {
+            await invoke("respond_keyboard_interactive", {
+              connectionId: prompt.connection_id,
+              responses,
+            });
+          } catch (error) {
+            console.error("Failed to answer authentication prompt:", error);
+          }
+        },
+
         // File operations
         loadDirectory: async (sessionId, path) => {
           const connectionState = get().activeSessions.get(sessionId);
@@ -492,7 +585,7 @@ const useSessionStore = create<SessionStore>()(
             set((state) => {
               const connState = state.activeSessions.get(sessionId);
               if (connState) {
-                connState.error = `Failed to load directory: ${error}`;
+                connState.error = `Failed to load directory: ${errorMessage(error)}`;
                 connState.isLoading = false;
               }
             });
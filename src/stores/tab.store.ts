@@ -7,6 +7,7 @@ import { FileItem } from "@/types/FileItem";
 import { invoke } from "@tauri-apps/api/core";
 import useSessionStore from "./session.store";
 import { getTabNameFromPath } from "@/utils/file.util";
+import { errorMessage } from "@/utils/error.util";
 
 interface TabStore {
   // State
@@ -61,6 +62,7 @@ interface TabStore {
   selectAllFiles: (tabId: string, files: string[]) => void;
   clearSelection: (tabId?: string) => void;
   setViewMode: (tabId: string, mode: ViewMode) => void;
+  setShowHidden: (tabId: string, showHidden: boolean) => void;
   setSorting: (tabId: string, sortBy: SortBy, sortOrder: SortOrder) => void;
   setFilter: (tabId: string, query: string) => void;
   setScrollPosition: (tabId: string, position: number) => void;
@@ -440,6 +442,7 @@ const useTabStore = create<TabStore>()(
             const files = (await invoke("list_directory", {
               connectionId: connectionId,
               path: path,
+              showHidden: tab.showHidden,
             })) as FileItem[];
 
             set((state) => {
@@ -459,7 +462,7 @@ const useTabStore = create<TabStore>()(
               const currentTab = state.tabs.find((t) => t.id === tabId);
               if (currentTab) {
                 currentTab.files = [];
-                currentTab.error = `Failed to load directory: ${error instanceof Error ? error.message : "Unknown error"}`;
+                currentTab.error = `Failed to load directory: ${errorMessage(error)}`;
                 currentTab.isLoading = false;
                 currentTab.scrollPosition = 0; // Reset scroll position on error
                 currentTab.selectedFiles = []; // Clear selection on error
@@ -658,6 +661,15 @@ const useTabStore = create<TabStore>()(
           get().updateTab(tabId, { viewMode: mode });
         },
 
+        setShowHidden: (tabId, showHidden) => {
+          get().updateTab(tabId, { showHidden });
+          // Dotfiles are filtered by the backend, so the listing is fetched again
+          const sessionId = get().getTabById(tabId)?.session?.id;
+          if (sessionId) {
+            get().loadDirectory(tabId, sessionId);
+          }
+        },
+
         setSorting: (tabId, sortBy, sortOrder) => {
           get().updateTab(tabId, { sortBy, sortOrder });
         },
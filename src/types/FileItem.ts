@@ -5,4 +5,15 @@ export interface FileItem {
   size: number;
   modified: number;
   permissions: string;
+  is_hidden: boolean;
+  is_symlink: boolean;
+  link_target: string | null;
+  target_type: "file" | "directory" | "symlink" | "other" | null;
+  is_broken_link: boolean;
+  mode: number; // Full st_mode, including the file type and special bits
+  uid: number | null;
+  gid: number | null;
+  owner: string | null;
+  group: string | null;
+  accessed: number;
 }
\ No newline at end of file
@@ -0,0 +1,11 @@
+// Questions a server asks during keyboard-interactive authentication, e.g. an OTP code
+export interface KeyboardInteractivePrompt {
+  connection_id: string;
+  host: string;
+  username: string;
+  instructions: string;
+  prompts: {
+    text: string;
+    echo: boolean; // Whether the answer may be shown while typing
+  }[];
+}
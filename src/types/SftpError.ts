@@ -0,0 +1,23 @@
+export type SftpErrorKind =
+  | "not_found"
+  | "permission_denied"
+  | "already_exists"
+  | "not_empty"
+  | "no_space"
+  | "connection_failed" // The server could not be reached or the handshake failed
+  | "connection_lost" // An established connection dropped
+  | "auth_failed"
+  | "host_key_unknown"
+  | "host_key_changed"
+  | "cancelled"
+  | "timeout"
+  | "unsupported"
+  | "invalid_input"
+  | "other";
+
+// Error every backend command rejects with
+export interface SftpError {
+  kind: SftpErrorKind;
+  message: string;
+  path: string | null; // Path the error is about, when one is involved
+}
@@ -1,9 +1,28 @@
+import { SftpError } from "./SftpError";
+
 export type Process = {
   connection_id: string;
   path: string;
   transferred: number;
   total: number;
-  type: "upload" | "download";
+  type: "upload" | "download" | "copy" | "chmod" | "chown";
   transfer_id: string;
-  status: "active" | "cancelled" | "completed";
+  status: "queued" | "active" | "paused" | "cancelled" | "completed" | "failed";
+  error?: string; // Why a failed transfer failed
+  // Totals of the whole tree, set for directory transfers
+  tree_transferred?: number;
+  tree_total?: number;
+  files_completed?: number;
+  files_total?: number;
+};
+
+
+// An entry of the backend transfer queue, as sent by transfer_queue_changed
+export type QueuedTransfer = {
+  transfer_id: string;
+  connection_id: string;
+  type: Process["type"];
+  path: string;
+  state: "queued" | "running" | "paused" | "completed" | "cancelled" | "failed";
+  error?: SftpError;
 };
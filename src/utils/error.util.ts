@@ -0,0 +1,12 @@
+import { SftpError } from "@/types/SftpError";
+
+export function isSftpError(error: unknown): error is SftpError {
+  return typeof error === "object" && error !== null && "kind" in error && "message" in error;
+}
+
+// Human readable message of anything a command or promise rejected with
+export function errorMessage(error: unknown): string {
+  if (isSftpError(error)) return error.message;
+  if (error instanceof Error) return error.message;
+  return String(error);
+}